@@ -0,0 +1,659 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::stock::{feature_index, feature_name, Period};
+
+// decimals percentages are rounded to without --precision, enough for the accuracy of a forest on a few thousand rows
+pub const PERCENT_DECIMALS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Increase,
+    Decrease,
+    // the forests are not confident enough to call a direction
+    Uncertain,
+}
+
+impl Direction {
+    /*
+        Returns the lowercase name of the direction used in reports
+
+        @param (&self) current direction
+
+        @return (&str) "increase", "decrease" or "uncertain"
+    */
+    pub fn get_name(&self) -> &str {
+        match self {
+            Direction::Increase => "increase",
+            Direction::Decrease => "decrease",
+            Direction::Uncertain => "uncertain",
+        }
+    }
+
+    /*
+        Looks up a direction by the name returned by get_name
+
+        @param (name: &str) "increase", "decrease" or "uncertain"
+
+        @return (Option<Direction>) the direction, None if the name is unknown
+    */
+    pub fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "increase" => Some(Direction::Increase),
+            "decrease" => Some(Direction::Decrease),
+            "uncertain" => Some(Direction::Uncertain),
+            _ => None,
+        }
+    }
+
+    /*
+        Returns the sign of the change the direction predicts, used to color it
+
+        @param (&self) current direction
+
+        @return (f64) 1 for an increase, -1 for a decrease and 0 when uncertain
+    */
+    pub fn get_sign(&self) -> f64 {
+        match self {
+            Direction::Increase => 1.0,
+            Direction::Decrease => -1.0,
+            Direction::Uncertain => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outlook {
+    Up,
+    Down,
+    // the current price is inside the band of the simulated prices, so no change is as likely as any
+    Flat,
+}
+
+impl Outlook {
+    /*
+        Classifies a Monte Carlo forecast by where the current price falls relative to a band of the
+        simulated prices
+
+        @param (current_price: f64) price the simulation started from
+        @param (band: (f64, f64)) lower and upper percentile of the simulated prices
+
+        @return (Outlook) Up if the whole band is above the current price, Down if it is below and Flat otherwise
+    */
+    pub fn classify(current_price: f64, band: (f64, f64)) -> Outlook {
+        if current_price < band.0 {
+            Outlook::Up
+        } else if current_price > band.1 {
+            Outlook::Down
+        } else {
+            Outlook::Flat
+        }
+    }
+
+    /*
+        Returns the lowercase name of the outlook used in reports
+
+        @param (&self) current outlook
+
+        @return (&str) "up", "down" or "flat"
+    */
+    pub fn get_name(&self) -> &str {
+        match self {
+            Outlook::Up => "up",
+            Outlook::Down => "down",
+            Outlook::Flat => "flat",
+        }
+    }
+
+    /*
+        Looks up an outlook by the name returned by get_name
+
+        @param (name: &str) "up", "down" or "flat"
+
+        @return (Option<Outlook>) the outlook, None if the name is unknown
+    */
+    pub fn from_name(name: &str) -> Option<Outlook> {
+        match name {
+            "up" => Some(Outlook::Up),
+            "down" => Some(Outlook::Down),
+            "flat" => Some(Outlook::Flat),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MoneyFormat {
+    // symbol written before every monetary value, may be empty
+    pub currency: String,
+    // group the digits before the decimal point in threes with commas
+    pub thousands: bool,
+    // decimals of prices and percentages set by --precision, None for two decimals on prices and up to
+    // PERCENT_DECIMALS on percentages
+    pub precision: Option<usize>,
+    // color increases green and decreases red with ANSI escapes, resolved from --color
+    pub color: bool,
+}
+
+impl MoneyFormat {
+    /*
+        Returns the number of decimals prices and rounded percentages are written with
+
+        @param (&self) current money format
+
+        @return (usize) the --precision, 2 if it is not set
+    */
+    pub fn decimals(&self) -> usize {
+        self.precision.unwrap_or(2)
+    }
+
+    /*
+        Colors text green for a positive sign and red for a negative one when color is on, text of a zero
+        sign is left plain. Pad the text before painting it, as the escapes count towards the width
+
+        @param (&self) current money format
+        @param (text: &str) text to color, such as a direction or a change
+        @param (sign: f64) value whose sign picks the color
+
+        @return (String) the text, wrapped in ANSI escapes if it is colored
+    */
+    pub fn paint(&self, text: &str, sign: f64) -> String {
+        if !self.color || sign == 0.0 || sign.is_nan() {
+            return text.to_string();
+        }
+
+        let code: &str = if sign > 0.0 { "32" } else { "31" };
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    }
+
+    /*
+        Formats a fraction as a percentage without the percent sign, rounded to the --precision if it is set
+        and otherwise to PERCENT_DECIMALS without trailing zeros
+
+        @param (&self) current money format
+        @param (fraction: f64) fraction such as a probability, e.g. 0.65
+
+        @return (String) percentage, e.g. 65 or 65.00 with a precision of 2
+    */
+    pub fn percent(&self, fraction: f64) -> String {
+        self.round_percent(fraction * 100.0)
+    }
+
+    /*
+        Formats a test accuracy as a percentage like percent, rounding it as an f32 so the digits the f32
+        does not hold are not printed
+
+        @param (&self) current money format
+        @param (accuracy: f32) test accuracy of the forests, e.g. 0.6
+
+        @return (String) percentage, e.g. 60 or 60.00 with a precision of 2
+    */
+    pub fn accuracy(&self, accuracy: f32) -> String {
+        self.round_percent(accuracy * 100.0)
+    }
+
+    /*
+        Rounds a percentage to the --precision, or to PERCENT_DECIMALS with the trailing zeros dropped
+
+        @param (&self) current money format
+        @param (percent: T) percentage as an f32 or f64, e.g. 65.714
+
+        @return (String) rounded percentage
+    */
+    fn round_percent<T: fmt::Display>(&self, percent: T) -> String {
+        match self.precision {
+            Some(precision) => format!("{:.*}", precision, percent),
+            None => {
+                let digits: String = format!("{:.*}", PERCENT_DECIMALS, percent);
+                digits
+                    .trim_end_matches('0')
+                    .trim_end_matches('.')
+                    .to_string()
+            }
+        }
+    }
+
+    /*
+        Formats a monetary value with the decimals of the --precision, the currency symbol and optional
+        thousands separators
+
+        @param (&self) current money format
+        @param (value: f64) monetary value such as a price
+
+        @return (String) formatted value, e.g. $1,234.57
+    */
+    pub fn format(&self, value: f64) -> String {
+        let digits: String = format!("{:.*}", self.decimals(), value.abs());
+        let (integer, decimals) = digits.split_at(digits.find('.').unwrap_or(digits.len()));
+
+        let mut grouped: String = String::new();
+        for (idx, digit) in integer.chars().enumerate() {
+            if self.thousands && idx > 0 && (integer.len() - idx) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+
+        let sign: &str = if value < 0.0 { "-" } else { "" };
+
+        format!("{}{}{}{}", sign, self.currency, grouped, decimals)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisResult {
+    pub filename: String,
+    pub rows: usize,
+    pub first_date: String,
+    pub last_date: String,
+    pub current_price: f64,
+    pub days: usize,
+    pub target_date: Option<NaiveDate>,
+    pub trials: usize,
+    pub predicted_price: f64,
+    // the simulation used a risk free rate, so the predicted price is a forward price
+    pub risk_neutral: bool,
+    // 5th and 95th percentiles of the simulated final day prices
+    pub confidence_interval: (f64, f64),
+    // 25th and 75th percentiles of the simulated final day prices
+    pub outlook_band: (f64, f64),
+    pub outlook: Outlook,
+    pub features: Vec<usize>,
+    // dates flagged by --detect-splits
+    pub split_dates: Vec<String>,
+    // SHA-256 of the parsed rows and the settings, see --provenance
+    pub provenance: String,
+    // length of one row of data
+    pub period: Period,
+    pub direction: Direction,
+    // minimum probability of either direction set by --prob-threshold
+    pub prob_threshold: Option<f64>,
+    // accuracy-weighted share of the forest vote for an increase, with --weighted-vote
+    pub weighted_increase: Option<f64>,
+    // average fraction of the trees predicting an increase
+    pub probability: f64,
+    pub accuracy: f32,
+    // test set counts summed over every forest, indexed by [actual label][predicted label]
+    pub confusion: [[usize; 2]; 2],
+}
+
+impl AnalysisResult {
+    /*
+        Calculates the predicted change of the price relative to the current price
+
+        @param (&self) current analysis result
+
+        @return (f64) predicted price / current price - 1, e.g. 0.05 for a 5% increase
+    */
+    pub fn expected_return(&self) -> f64 {
+        self.predicted_price / self.current_price - 1.0
+    }
+
+    /*
+        Renders the analysis of one file as the sentences printed in text mode
+
+        @param (&self) current analysis result
+        @param (money: &MoneyFormat) format of the prices
+
+        @return (String) the Monte Carlo forecast, its outlook and the random forest prediction, one per line
+    */
+    pub fn to_text(&self, money: &MoneyFormat) -> String {
+        // risk-neutral paths give the forward price, not the expected future price
+        let price_kind: &str = if self.risk_neutral {
+            "risk-neutral forward price"
+        } else {
+            "price"
+        };
+
+        let mut text: String = match self.target_date {
+            Some(target) => format!(
+                "Monte Carlo methods predict a {} of {} on {}!\n",
+                price_kind,
+                money.format(self.predicted_price),
+                target
+            ),
+            // day 0 of the simulation is the current price
+            None => format!(
+                "Monte Carlo methods predict a {} of {} after {} days!\n",
+                price_kind,
+                money.format(self.predicted_price),
+                self.days - 1
+            ),
+        };
+
+        text.push_str(&match self.outlook {
+            Outlook::Flat => format!(
+                "The forecast is flat, the current price is inside the 50% band of {} to {}\n",
+                money.format(self.outlook_band.0),
+                money.format(self.outlook_band.1)
+            ),
+            _ => format!(
+                "The forecast is {}, the 50% band of {} to {} excludes the current price\n",
+                money.paint(self.outlook.get_name(), self.expected_return()),
+                money.format(self.outlook_band.0),
+                money.format(self.outlook_band.1)
+            ),
+        });
+
+        if self.prob_threshold.is_some() {
+            text.push_str(&format!(
+                "The Random Forest gives a {}% probability of an increase\n",
+                money.percent(self.probability)
+            ));
+        }
+
+        if let Some(weighted) = self.weighted_increase {
+            text.push_str(&format!(
+                "Weighted by test accuracy, {:.*}% of the forest vote is for an increase\n",
+                money.decimals(),
+                weighted * 100.0
+            ));
+        }
+
+        text.push_str(&match self.direction {
+            Direction::Increase => format!(
+                "The Random Forest predicts {} with a test accuracy of {}%!\n",
+                money.paint("an increase", 1.0),
+                money.accuracy(self.accuracy)
+            ),
+            Direction::Decrease => format!(
+                "The Random Forest predicts {} with a test accuracy of {}!\n",
+                money.paint("a decrease", -1.0),
+                money.accuracy(self.accuracy)
+            ),
+            Direction::Uncertain => format!(
+                "The Random Forest is uncertain about the direction with a test accuracy of {}%!\n",
+                money.accuracy(self.accuracy)
+            ),
+        });
+
+        text
+    }
+
+    /*
+        Converts the analysis of one file to JSON with every field, prices are not formatted
+
+        @param (&self) current analysis result
+
+        @return (Value) JSON object with one key per field
+    */
+    pub fn to_json(&self) -> Value {
+        let feature_names: Vec<&str> = self.features.iter().map(|idx| feature_name(*idx)).collect();
+
+        json!({
+            "file": self.filename,
+            "rows": self.rows,
+            "first_date": self.first_date,
+            "last_date": self.last_date,
+            "current_price": self.current_price,
+            "days": self.days,
+            "target_date": self.target_date.map(|date| date.to_string()),
+            "trials": self.trials,
+            "predicted_price": self.predicted_price,
+            "risk_neutral": self.risk_neutral,
+            "confidence_interval": [self.confidence_interval.0, self.confidence_interval.1],
+            "outlook_band": [self.outlook_band.0, self.outlook_band.1],
+            "outlook": self.outlook.get_name(),
+            "features": feature_names,
+            "split_dates": self.split_dates,
+            "provenance": self.provenance,
+            "period": self.period.get_label(),
+            "direction": self.direction.get_name(),
+            "prob_threshold": self.prob_threshold,
+            "weighted_increase": self.weighted_increase,
+            "probability": self.probability,
+            "accuracy": self.accuracy,
+            "confusion": self.confusion,
+        })
+    }
+
+    /*
+        Reads back an analysis result written by to_json
+
+        @param (value: &Value) JSON object with one key per field
+
+        @return (Option<AnalysisResult>) the analysis result, None if a field is missing or malformed
+    */
+    pub fn from_json(value: &Value) -> Option<AnalysisResult> {
+        let text = |key: &str| value[key].as_str().map(String::from);
+        let count = |key: &str| value[key].as_u64().map(|count| count as usize);
+        let pair = |key: &str| Some((value[key][0].as_f64()?, value[key][1].as_f64()?));
+        let cell = |actual: usize, predicted: usize| {
+            value["confusion"][actual][predicted]
+                .as_u64()
+                .map(|count| count as usize)
+        };
+
+        Some(AnalysisResult {
+            filename: text("file")?,
+            rows: count("rows")?,
+            first_date: text("first_date")?,
+            last_date: text("last_date")?,
+            current_price: value["current_price"].as_f64()?,
+            days: count("days")?,
+            target_date: match value["target_date"].as_str() {
+                Some(date) => Some(date.parse().ok()?),
+                None => None,
+            },
+            trials: count("trials")?,
+            predicted_price: value["predicted_price"].as_f64()?,
+            risk_neutral: value["risk_neutral"].as_bool()?,
+            confidence_interval: pair("confidence_interval")?,
+            outlook_band: pair("outlook_band")?,
+            outlook: Outlook::from_name(value["outlook"].as_str()?)?,
+            features: value["features"]
+                .as_array()?
+                .iter()
+                .map(|name| feature_index(name.as_str()?))
+                .collect::<Option<_>>()?,
+            split_dates: value["split_dates"]
+                .as_array()?
+                .iter()
+                .map(|date| date.as_str().map(String::from))
+                .collect::<Option<_>>()?,
+            provenance: text("provenance")?,
+            period: Period::from_label(value["period"].as_str()?)?,
+            direction: Direction::from_name(value["direction"].as_str()?)?,
+            prob_threshold: value["prob_threshold"].as_f64(),
+            weighted_increase: value["weighted_increase"].as_f64(),
+            probability: value["probability"].as_f64()?,
+            accuracy: value["accuracy"].as_f64()? as f32,
+            confusion: [[cell(0, 0)?, cell(0, 1)?], [cell(1, 0)?, cell(1, 1)?]],
+        })
+    }
+
+    /*
+        Renders the analysis of one file as a Markdown section
+
+        @param (&self) current analysis result
+        @param (money: &MoneyFormat) format of the prices
+
+        @return (String) Markdown section with the data summary, forecast, forest metrics and parameters
+    */
+    pub fn to_markdown(&self, money: &MoneyFormat) -> String {
+        let mut markdown: String = format!("## {}\n\n", self.filename);
+
+        markdown.push_str("### Data Summary\n\n");
+        markdown.push_str(&format!("- Rows: {}\n", self.rows));
+        markdown.push_str(&format!(
+            "- Date range: {} to {}\n",
+            self.first_date, self.last_date
+        ));
+        if !self.split_dates.is_empty() {
+            markdown.push_str(&format!(
+                "- Possible splits: {}\n",
+                self.split_dates.join(", ")
+            ));
+        }
+        markdown.push_str(&format!(
+            "- Current price: {}\n\n",
+            money.format(self.current_price)
+        ));
+
+        markdown.push_str("### Monte Carlo Forecast\n\n");
+        markdown.push_str("| Statistic | Price |\n|---|---|\n");
+        markdown.push_str(&format!(
+            "| Mean | {} |\n",
+            money.format(self.predicted_price)
+        ));
+        markdown.push_str(&format!(
+            "| 5th percentile | {} |\n",
+            money.format(self.confidence_interval.0)
+        ));
+        markdown.push_str(&format!(
+            "| 95th percentile | {} |\n\n",
+            money.format(self.confidence_interval.1)
+        ));
+        markdown.push_str(&format!(
+            "Outlook: {}, the 50% band of the simulated prices is {} to {}\n\n",
+            self.outlook.get_name(),
+            money.format(self.outlook_band.0),
+            money.format(self.outlook_band.1)
+        ));
+
+        markdown.push_str("### Random Forest\n\n");
+        markdown.push_str(&format!("- Direction: {}\n", self.direction.get_name()));
+        markdown.push_str(&format!(
+            "- Probability of an increase: {:.*}%\n",
+            money.decimals(),
+            self.probability * 100.0
+        ));
+        markdown.push_str(&format!(
+            "- Test accuracy: {:.*}%\n\n",
+            money.decimals(),
+            self.accuracy * 100.0
+        ));
+        markdown.push_str("| Actual \\ Predicted | Decrease | Increase |\n|---|---|---|\n");
+        markdown.push_str(&format!(
+            "| Decrease | {} | {} |\n",
+            self.confusion[0][0], self.confusion[0][1]
+        ));
+        markdown.push_str(&format!(
+            "| Increase | {} | {} |\n\n",
+            self.confusion[1][0], self.confusion[1][1]
+        ));
+
+        let feature_names: Vec<&str> = self.features.iter().map(|idx| feature_name(*idx)).collect();
+
+        markdown.push_str("### Parameters\n\n");
+        markdown.push_str(&format!("- Period: {}\n", self.period.get_label()));
+        markdown.push_str(&format!("- Simulated days: {}\n", self.days));
+        if let Some(target) = self.target_date {
+            markdown.push_str(&format!("- Target date: {}\n", target));
+        }
+        markdown.push_str(&format!("- Monte Carlo trials: {}\n", self.trials));
+        markdown.push_str(&format!(
+            "- Forest features: {}\n",
+            feature_names.join(", ")
+        ));
+        markdown.push_str(&format!("- Provenance: {}\n", self.provenance));
+
+        markdown
+    }
+}
+
+impl fmt::Display for AnalysisResult {
+    // the text mode output with plain prices, see to_text
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_text(&MoneyFormat::default()))
+    }
+}
+
+/*
+    Formats the results as a table with one row per file, numbers are right aligned with two decimals
+
+    @param (results: &[AnalysisResult]) analysis results of the files that were processed
+    @param (money: &MoneyFormat) format of the prices
+
+    @return (String) table with a header row followed by one row per result
+*/
+pub fn format_table(results: &[AnalysisResult], money: &MoneyFormat) -> String {
+    let file_width: usize = results
+        .iter()
+        .map(|result| result.filename.len())
+        .chain(std::iter::once(4))
+        .max()
+        .unwrap();
+
+    let price_width: usize = results
+        .iter()
+        .flat_map(|result| [result.current_price, result.predicted_price])
+        .map(|price| money.format(price).len())
+        .chain(std::iter::once(10))
+        .max()
+        .unwrap();
+
+    let mut table: String = format!(
+        "{:<file_width$}  {:>price_width$}  {:>price_width$}  {:>9}  {:>9}  {:>9}\n",
+        "File", "Current", "Predicted", "Change %", "Direction", "Accuracy"
+    );
+
+    for result in results {
+        let change: f64 = result.expected_return() * 100.0;
+        table.push_str(&format!(
+            "{:<file_width$}  {:>price_width$}  {:>price_width$}  {}  {}  {:>8.decimals$}%\n",
+            result.filename,
+            money.format(result.current_price),
+            money.format(result.predicted_price),
+            money.paint(&format!("{:>9.*}", money.decimals(), change), change),
+            money.paint(
+                &format!("{:>9}", result.direction.get_name()),
+                result.direction.get_sign()
+            ),
+            result.accuracy * 100.0,
+            decimals = money.decimals()
+        ));
+    }
+
+    table
+}
+
+/*
+    Formats the results as one table per predicted direction, increases first, each sorted by the size of
+    the predicted change so the strongest candidates come first
+
+    @param (results: &[AnalysisResult]) analysis results of the files that were processed
+    @param (money: &MoneyFormat) format of the prices
+
+    @return (String) a titled table for every direction with at least one result
+*/
+pub fn format_grouped(results: &[AnalysisResult], money: &MoneyFormat) -> String {
+    let mut grouped: String = String::new();
+
+    for direction in [
+        Direction::Increase,
+        Direction::Decrease,
+        Direction::Uncertain,
+    ] {
+        let mut group: Vec<AnalysisResult> = results
+            .iter()
+            .filter(|result| result.direction == direction)
+            .cloned()
+            .collect();
+        if group.is_empty() {
+            continue;
+        }
+
+        group.sort_by(|a, b| {
+            b.expected_return()
+                .abs()
+                .total_cmp(&a.expected_return().abs())
+        });
+
+        if !grouped.is_empty() {
+            grouped.push('\n');
+        }
+        grouped.push_str(&format!(
+            "Predicted {} ({}):\n",
+            direction.get_name(),
+            group.len()
+        ));
+        grouped.push_str(&format_table(&group, money));
+    }
+
+    grouped
+}
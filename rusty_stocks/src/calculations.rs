@@ -1,48 +1,187 @@
 use rand::distributions::Distribution;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use randomforest::{RandomForestClassifier, RandomForestClassifierOptions};
-use randomforest::criterion::Gini;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::num::NonZeroUsize;
+
+use chrono::{Datelike, NaiveDate, ParseResult};
+use randomforest::criterion::{Entropy, Gini};
 use randomforest::table::{Table, TableBuilder};
+use randomforest::{RandomForestClassifier, RandomForestClassifierOptions};
 use statrs::distribution::Normal;
 
-use crate::stock::Stock;
+use crate::stock::{ReturnType, Stock, Tomorrow};
+use crate::CustomResult;
+
+// number of Monte Carlo random walks simulated for each stock without --trials
+pub const TRIALS: usize = 50000;
+
+// default number of random forests trained and voting on the prediction of each stock, see --forest-max-votes
+pub const FOREST_VOTES: usize = 10;
+
+// number of days, including the current one, a volume is ranked against by bucket_volumes
+pub const VOLUME_BUCKET_WINDOW: usize = 20;
+
+// copies of the newest training row made by --recency-weight, older rows get fewer down to one
+pub const RECENCY_COPIES: usize = 5;
+
+// fewest rows a training table is fitted on
+pub const MIN_TRAINING_ROWS: usize = 2;
+
+// number of folds used by cross_validate
+pub const CV_FOLDS: usize = 5;
+
+// number of closes averaged by the short and long moving averages of engineer_features
+pub const SMA_SHORT_WINDOW: usize = 5;
+pub const SMA_LONG_WINDOW: usize = 20;
+
+// number of daily close changes the RSI of engineer_features is computed over
+pub const RSI_WINDOW: usize = 14;
 
 /*
     Constructs a random forest crate TableBuilder which holds the stock data from
     the passed stock struct vector
 
     @param (stocks: &Vec<Stock>) vector of stock structs containing training dataset
+    @param (features: &[usize]) indices into FEATURE_NAMES of the features the forest is trained on
 
     @return (TableBuilder) TableBuilder object with stock data inserted
 */
-pub fn construct_table(stocks: &Vec<Stock>) -> TableBuilder {
+pub fn construct_table(stocks: &Vec<Stock>, features: &[usize]) -> TableBuilder {
     let mut table_builder: TableBuilder = TableBuilder::new();
 
     for stock in stocks {
-        let _ = table_builder.add_row(&stock.get_array(), stock.get_label());
+        let _ = table_builder.add_row(&stock.feature_subset(features), stock.get_label());
     }
 
     table_builder
 }
 
+/*
+    Checks that a training set has enough rows and both labels before a forest is fitted on it, as the random
+    forest crate fits a meaningless model to a table of one label
+
+    @param (training_set: &[Stock]) stocks of the training table, including the copies of --recency-weight
+
+    @return (CustomResult<()>) error if the training set has fewer than MIN_TRAINING_ROWS rows or only one label
+*/
+pub fn check_training_set(training_set: &[Stock]) -> CustomResult<()> {
+    if training_set.len() < MIN_TRAINING_ROWS {
+        return Err(format!(
+            "the training table has {} rows, at least {} are needed",
+            training_set.len(),
+            MIN_TRAINING_ROWS
+        )
+        .into());
+    }
+
+    let first_label: f64 = training_set[0].get_label();
+    if training_set
+        .iter()
+        .all(|stock| stock.get_label() == first_label)
+    {
+        return Err(format!(
+            "every row of the training table is labeled {}, a forest needs both increases and decreases",
+            Tomorrow::from_label(first_label).map_or("the same", |tomorrow| {
+                if tomorrow.is_increase() {
+                    "an increase"
+                } else {
+                    "a decrease"
+                }
+            })
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/*
+    Counts the copies of a training row which approximate a weight of exp(-lambda * age), as the random
+    forest crate does not take sample weights. The newest row gets RECENCY_COPIES and every row at least one
+
+    @param (age: usize) number of rows between the training row and the newest one
+    @param (lambda: f64) decay of the weight per row of age, 0 weighs every row alike
+
+    @return (usize) number of times the row is added to the training table
+*/
+pub fn recency_copies(age: usize, lambda: f64) -> usize {
+    let weight: f64 = (-lambda * age as f64).exp();
+
+    ((RECENCY_COPIES as f64 * weight).round() as usize).max(1)
+}
+
+/*
+    Adds the rows of a dataset to a training set, repeated by recency_copies when a recency weight is given
+
+    @param (training_set: &mut Vec<Stock>) training set the rows are added to
+    @param (dataset: &[Stock]) stocks in date order
+    @param (idx: usize) index of the row in the dataset
+    @param (recency_weight: Option<f64>) decay of the weight per row of age, set by --recency-weight
+
+    @return (usize) number of copies of the row that were added
+*/
+fn push_training_row(
+    training_set: &mut Vec<Stock>,
+    dataset: &[Stock],
+    idx: usize,
+    recency_weight: Option<f64>,
+) -> usize {
+    let copies: usize =
+        recency_weight.map_or(1, |lambda| recency_copies(dataset.len() - 1 - idx, lambda));
+
+    for _ in 0..copies {
+        training_set.push(dataset[idx].clone());
+    }
+
+    copies
+}
+
+/*
+    Calculates how many distinct training rows a tree is expected to leave out of its bootstrap sample. The
+    forest crate draws as many rows with replacement as the training table has, so a row with c of the N
+    copies in the table is left out with probability (1 - c / N)^N, about 37% for a row without copies
+
+    @param (copies: &[usize]) number of copies of each distinct row in the training table
+
+    @return (f64) expected number of distinct rows out of bag
+*/
+pub fn expected_out_of_bag(copies: &[usize]) -> f64 {
+    let table_rows: f64 = copies.iter().sum::<usize>() as f64;
+
+    copies
+        .iter()
+        .map(|count| (1.0 - *count as f64 / table_rows).powf(table_rows))
+        .sum()
+}
+
 /*
     Splits stocks into two sets, training and testing for cross-reference testing
 
-    @param (stocks: &Vec<stock>) vector of stock structs parsed from file
+    @param (stocks: &[T]) vector of stock structs parsed from file, or of their indices
     @param (training: f32) fraction of dataset to be in the training set
+    @param (rng: &mut impl Rng) random number generator used to shuffle the dataset
 
-    @return (Vec<Stock>, Vec<Stock) partitioned training and testing datasets respectively
+    @return (Vec<T>, Vec<T>) partitioned training and testing datasets respectively, with at least
+    one stock in each set when there are two or more stocks so the test accuracy is never 0 / 0
 */
-pub fn split_data(stocks: &[Stock], training: f32) -> (Vec<Stock>, Vec<Stock>) {
+pub fn split_data<T: Clone>(stocks: &[T], training: f32, rng: &mut impl Rng) -> (Vec<T>, Vec<T>) {
     let mut indices: Vec<usize> = (0..stocks.len()).collect();
-    indices.shuffle(&mut rand::thread_rng());
-    let training_index: usize = (training * (stocks.len() as f32)) as usize;
-    let mut training_set: Vec<Stock> = Vec::new();
+    indices.shuffle(rng);
+    let mut training_index: usize = (training * (stocks.len() as f32)) as usize;
+    if stocks.len() >= 2 {
+        training_index = training_index.clamp(1, stocks.len() - 1);
+    }
+    let mut training_set: Vec<T> = Vec::new();
     for idx in indices[0..training_index].iter() {
         training_set.push(stocks[*idx].clone());
     }
 
-    let mut test_set: Vec<Stock> = Vec::new();
+    let mut test_set: Vec<T> = Vec::new();
     for idx in indices[training_index..].iter() {
         test_set.push(stocks[*idx].clone());
     }
@@ -50,62 +189,1147 @@ pub fn split_data(stocks: &[Stock], training: f32) -> (Vec<Stock>, Vec<Stock>) {
     (training_set, test_set)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitCriterion {
+    Gini,
+    Entropy,
+}
+
+impl SplitCriterion {
+    /*
+        Returns the name of the criterion as used on the command line
+
+        @param (&self) current split criterion
+
+        @return (&str) "gini" or "entropy"
+    */
+    pub fn get_name(&self) -> &str {
+        match self {
+            SplitCriterion::Gini => "gini",
+            SplitCriterion::Entropy => "entropy",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VolumeTransform {
+    // the raw number of shares
+    None,
+    // ln(1 + volume)
+    Log,
+    // fraction of the training volumes below the volume
+    Rank,
+}
+
+// every volume transform, in the order their accuracies are reported
+pub const VOLUME_TRANSFORMS: [VolumeTransform; 3] = [
+    VolumeTransform::None,
+    VolumeTransform::Log,
+    VolumeTransform::Rank,
+];
+
+impl VolumeTransform {
+    /*
+        Returns the name of the transform as used on the command line
+
+        @param (&self) current volume transform
+
+        @return (&str) "none", "log" or "rank"
+    */
+    pub fn get_name(&self) -> &str {
+        match self {
+            VolumeTransform::None => "none",
+            VolumeTransform::Log => "log",
+            VolumeTransform::Rank => "rank",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeMode {
+    // append the rows of every file as they are
+    Concat,
+    // scale the prices of every file to continue the series before it without a jump
+    Chain,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForestOptions {
+    // indices into FEATURE_NAMES of the features the forest is trained on
+    pub features: Vec<usize>,
+    // flip the prediction of forests with a test accuracy below 50%
+    pub invert: bool,
+    // number of decision trees in the forest
+    pub trees: usize,
+    // number of features each split chooses from, None for the square root of the feature count
+    pub max_features: Option<usize>,
+    pub criterion: SplitCriterion,
+    // fraction of the stocks before the predicted one that the forest is trained on
+    pub train_frac: f32,
+    // decay per row of age of the weight of a training row, see recency_copies
+    pub recency_weight: Option<f64>,
+    // most forests trained for one vote
+    pub max_votes: usize,
+    // stop training forests once the average test accuracy changes by less than this, if set
+    pub target_stability: Option<f64>,
+}
+
+/*
+    Fits a random forest classifier with the tree count, max features and criterion of the options
+
+    @param (table: Table) training table built by construct_table
+    @param (options: &ForestOptions) hyperparameters of the forest
+    @param (rng: &mut impl Rng) random number generator the bootstrap samples and splits of the trees are seeded from
+
+    @return (RandomForestClassifier) fitted classifier
+*/
+fn fit_forest(table: Table, options: &ForestOptions, rng: &mut impl Rng) -> RandomForestClassifier {
+    let mut classifier_options: RandomForestClassifierOptions =
+        RandomForestClassifierOptions::new();
+    classifier_options.seed(rng.gen());
+
+    if let Some(trees) = NonZeroUsize::new(options.trees) {
+        classifier_options.trees(trees);
+    }
+
+    if let Some(max_features) = options.max_features.and_then(NonZeroUsize::new) {
+        classifier_options.max_features(max_features);
+    }
+
+    match options.criterion {
+        SplitCriterion::Gini => classifier_options.fit(Gini, table),
+        SplitCriterion::Entropy => classifier_options.fit(Entropy, table),
+    }
+}
+
+/*
+    Predicts the label of a row by the majority of the trees of a classifier. The predict of the classifier
+    breaks ties in the order of a HashMap, which changes from run to run even with a seed, so here a tie counts
+    as an increase like in a ForestVote
+
+    @param (classifier: &RandomForestClassifier) fitted classifier
+    @param (values: &[f64]) values of the features the classifier was trained on
+
+    @return (f64) label of the direction most trees predict
+*/
+fn majority_label(classifier: &RandomForestClassifier, values: &[f64]) -> f64 {
+    let (increases, trees): (usize, usize) =
+        classifier
+            .predict_individuals(values)
+            .fold((0, 0), |(increases, trees), prediction| {
+                let increase: bool =
+                    Tomorrow::from_label(prediction).is_some_and(|tomorrow| tomorrow.is_increase());
+                (increases + increase as usize, trees + 1)
+            });
+
+    if 2 * increases >= trees {
+        Tomorrow::Increase.get_label()
+    } else {
+        Tomorrow::Decrease.get_label()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ForestResult {
+    pub prediction: f64,
+    // fraction of the trees predicting an increase, after flipping an inaccurate forest
+    pub probability: f64,
+    pub accuracy: f32,
+    // test set counts indexed by [actual label][predicted label], 0 for decrease and 1 for increase
+    pub confusion: [[usize; 2]; 2],
+    // whether the prediction was flipped because the test accuracy was below 50%
+    pub inverted: bool,
+}
+
+#[derive(Debug)]
+pub struct TrainedForest {
+    classifier: RandomForestClassifier,
+    // test accuracy, after flipping an inaccurate forest
+    accuracy: f32,
+    // test set counts indexed by [actual label][predicted label], 0 for decrease and 1 for increase
+    confusion: [[usize; 2]; 2],
+    // whether predictions are flipped because the test accuracy was below 50%
+    inverted: bool,
+    // distinct training rows and the expected number each tree leaves out of bag, None for a loaded model
+    out_of_bag: Option<(usize, f64)>,
+}
+
+impl TrainedForest {
+    /*
+        Trains a random forest on a random split of the stocks before predict_index and measures it on the rest
+
+        @param (stocks: &[Stock]) vector of Stock objects parsed from the input file
+        @param (options: &ForestOptions) features to train on and whether to flip inaccurate forests
+        @param (predict_index: usize) index of the stock to predict, only the stocks before it are used for training
+        @param (rng: &mut impl Rng) random number generator of the split and the forest
+
+        @return (CustomResult<TrainedForest>) CustomResult containing the fitted classifier with its test accuracy
+        and confusion matrix, or the error of building its training table
+    */
+    pub fn train(
+        stocks: &[Stock],
+        options: &ForestOptions,
+        predict_index: usize,
+        rng: &mut impl Rng,
+    ) -> CustomResult<TrainedForest> {
+        let features: &[usize] = &options.features;
+        let dataset: &[Stock] = &stocks[0..predict_index];
+        let rows: Vec<usize> = (0..predict_index).collect();
+
+        let (training_rows, test_rows) = split_data(&rows, options.train_frac, rng);
+
+        let mut training_set: Vec<Stock> = Vec::new();
+        let copies: Vec<usize> = training_rows
+            .iter()
+            .map(|idx| push_training_row(&mut training_set, dataset, *idx, options.recency_weight))
+            .collect();
+        let test_set: Vec<Stock> = test_rows.iter().map(|idx| dataset[*idx].clone()).collect();
+
+        check_training_set(&training_set)?;
+        let table_builder: TableBuilder = construct_table(&training_set, features);
+
+        let table: Table = table_builder
+            .build()
+            .map_err(|e| format!("could not build the training table: {}", e))?;
+
+        let classifier: RandomForestClassifier = fit_forest(table, options, rng);
+
+        let num_tests: f32 = test_set.len() as f32;
+        let mut num_correct: f32 = 0.0;
+        let mut confusion: [[usize; 2]; 2] = [[0; 2]; 2];
+
+        for stock in test_set {
+            let result = majority_label(&classifier, &stock.feature_subset(features));
+
+            if result == stock.get_label() {
+                num_correct += 1.0;
+            }
+
+            confusion[stock.get_label() as usize][result as usize] += 1;
+        }
+
+        let mut accuracy = num_correct / num_tests;
+        let mut switch_flag: bool = false;
+
+        // if the accuracy is less than 50% it is actually useful to do the opposite of what the model says
+        if options.invert && accuracy < 0.5 {
+            accuracy = 1.0 - accuracy;
+            switch_flag = true;
+
+            for row in confusion.iter_mut() {
+                row.swap(0, 1);
+            }
+        }
+
+        Ok(TrainedForest {
+            classifier,
+            accuracy,
+            confusion,
+            inverted: switch_flag,
+            out_of_bag: Some((copies.len(), expected_out_of_bag(&copies))),
+        })
+    }
+
+    /*
+        Getter for the bootstrap sampling of the forest, which is only known for a forest trained in this run
+
+        @param (&self) current trained forest
+
+        @return (Option<(usize, f64)>) number of distinct training rows and the expected number of them each
+        tree leaves out of its bootstrap sample, None for a forest loaded from a model file
+    */
+    pub fn get_out_of_bag(&self) -> Option<(usize, f64)> {
+        self.out_of_bag
+    }
+
+    /*
+        Predicts if the stock will increase or decrease between its day and the next
+
+        @param (&self) current trained forest
+        @param (stock: &Stock) stock to predict
+        @param (features: &[usize]) indices of the features the forest was trained on
+
+        @return (ForestResult) the predicted result, accuracy and confusion matrix of the test set
+    */
+    pub fn predict(&self, stock: &Stock, features: &[usize]) -> ForestResult {
+        let mut result = majority_label(&self.classifier, &stock.feature_subset(features));
+        let probability: f64 = self.increase_probability(&stock.feature_subset(features));
+
+        if self.inverted {
+            result = match Tomorrow::from_label(result) {
+                Some(Tomorrow::Increase) => Tomorrow::Decrease.get_label(),
+                _ => Tomorrow::Increase.get_label(),
+            };
+        }
+
+        ForestResult {
+            prediction: result,
+            probability,
+            accuracy: self.accuracy,
+            confusion: self.confusion,
+            inverted: self.inverted,
+        }
+    }
+
+    /*
+        Calculates the fraction of the trees voting for an increase, flipped for an inverted forest
+
+        @param (&self) current trained forest
+        @param (values: &[f64]) values of the features the forest was trained on
+
+        @return (f64) probability of an increase between 0 and 1
+    */
+    pub fn increase_probability(&self, values: &[f64]) -> f64 {
+        let tree_predictions: Vec<f64> = self.classifier.predict_individuals(values).collect();
+        let probability: f64 = tree_predictions
+            .iter()
+            .filter(|prediction| {
+                Tomorrow::from_label(**prediction).is_some_and(|tomorrow| tomorrow.is_increase())
+            })
+            .count() as f64
+            / tree_predictions.len() as f64;
+
+        if self.inverted {
+            1.0 - probability
+        } else {
+            probability
+        }
+    }
+
+    /*
+        Writes the forest and its test metrics to a byte stream
+
+        @param (&self) current trained forest
+        @param (writer: &mut W) destination of the bytes
+
+        @return (io::Result<()>) error of the writer, if any
+    */
+    pub fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.accuracy.to_le_bytes())?;
+        for count in self.confusion.iter().flatten() {
+            writer.write_all(&(*count as u64).to_le_bytes())?;
+        }
+        writer.write_all(&[self.inverted as u8])?;
+
+        self.classifier.serialize(writer)
+    }
+
+    /*
+        Reads a forest written by serialize
+
+        @param (reader: &mut R) source of the bytes
+
+        @return (io::Result<TrainedForest>) the forest, or the error of the reader or of malformed data
+    */
+    pub fn deserialize<R: Read>(reader: &mut R) -> io::Result<TrainedForest> {
+        let mut accuracy: [u8; 4] = [0; 4];
+        reader.read_exact(&mut accuracy)?;
+
+        let mut confusion: [[usize; 2]; 2] = [[0; 2]; 2];
+        for count in confusion.iter_mut().flatten() {
+            let mut bytes: [u8; 8] = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            *count = u64::from_le_bytes(bytes) as usize;
+        }
+
+        let mut inverted: [u8; 1] = [0; 1];
+        reader.read_exact(&mut inverted)?;
+
+        Ok(TrainedForest {
+            classifier: RandomForestClassifier::deserialize(reader)?,
+            accuracy: f32::from_le_bytes(accuracy),
+            confusion,
+            inverted: inverted[0] != 0,
+            out_of_bag: None,
+        })
+    }
+}
+
 /*
     Builds the random forest and predicts if it will increase or decrease between today and tomorrow
 
     @param (stocks: Vec<Stock>) vector of Stock objects parsed from the input file
+    @param (options: &ForestOptions) features to train on and whether to flip inaccurate forests
+    @param (predict_index: usize) index of the stock to predict, only the stocks before it are used for training
+    @param (rng: &mut impl Rng) random number generator of the split and the forest
+
+    @return (CustomResult<ForestResult>) CustomResult containing the predicted result, accuracy and confusion
+    matrix of the test set, or the error of building the training table
+*/
+pub fn run_forest(
+    stocks: &[Stock],
+    options: &ForestOptions,
+    predict_index: usize,
+    rng: &mut impl Rng,
+) -> CustomResult<ForestResult> {
+    Ok(TrainedForest::train(stocks, options, predict_index, rng)?
+        .predict(&stocks[predict_index], &options.features))
+}
+
+#[derive(Debug, Clone)]
+pub struct ForestVote {
+    pub num_inc: i32,
+    pub num_dec: i32,
+    // share of the votes for an increase when each forest's vote is weighted by its test accuracy
+    pub weighted_increase: f64,
+    // average probability of an increase given by the voting forests
+    pub probability: f64,
+    // average test accuracy of the voting forests
+    pub accuracy: f32,
+    // average test accuracy of the voting forests before inaccurate ones are flipped
+    pub raw_accuracy: f32,
+    // test set counts summed over every forest, indexed by [actual label][predicted label]
+    pub confusion: [[usize; 2]; 2],
+}
+
+impl ForestVote {
+    /*
+        Checks if the majority of the forests predicted an increase, ties count as an increase
+
+        @param (&self) current forest vote
+
+        @return (bool) true if at least half of the forests predicted an increase
+    */
+    pub fn is_increase(&self) -> bool {
+        self.num_inc >= self.num_dec
+    }
+
+    /*
+        Checks if the forests predicting an increase have at least half of the accuracy-weighted vote, ties
+        count as an increase
+
+        @param (&self) current forest vote
+
+        @return (bool) true if weighted_increase is at least 0.5
+    */
+    pub fn is_weighted_increase(&self) -> bool {
+        self.weighted_increase >= 0.5
+    }
+
+    /*
+        Calculates the meta-confidence of the vote, the share of the forests agreeing with the majority. Each
+        forest is itself the majority of its trees, so a vote of 10 to 0 is stable across training runs while
+        a vote of 6 to 4 could flip on another split
+
+        @param (&self) current forest vote
+
+        @return (f64) fraction of the forests on the side of the majority, between 0.5 and 1
+    */
+    pub fn agreement(&self) -> f64 {
+        self.num_inc.max(self.num_dec) as f64 / (self.num_inc + self.num_dec) as f64
+    }
+}
+
+/*
+    Trains the random forests which vote on a prediction, each on a different split. Up to max_votes forests
+    are trained, fewer when a target stability is set and the average test accuracy of the forests so far
+    changes by less than it when one more forest is added
+
+    @param (stocks: &[Stock]) vector of Stock objects parsed from the input file
+    @param (options: &ForestOptions) features to train on, whether to flip inaccurate forests and when to stop
+    @param (predict_index: usize) index of the stock to predict, only the stocks before it are used for training
+    @param (rng: &mut impl Rng) random number generator of the splits and the forests
+
+    @return (CustomResult<Vec<TrainedForest>>) CustomResult containing the trained forests, or the error of
+    building a training table
+*/
+pub fn train_votes(
+    stocks: &[Stock],
+    options: &ForestOptions,
+    predict_index: usize,
+    rng: &mut impl Rng,
+) -> CustomResult<Vec<TrainedForest>> {
+    let mut forests: Vec<TrainedForest> = Vec::new();
+    let mut total_accuracy: f64 = 0.0;
+
+    while forests.len() < options.max_votes {
+        let previous_mean: f64 = total_accuracy / forests.len() as f64;
+
+        let forest: TrainedForest = TrainedForest::train(stocks, options, predict_index, rng)?;
+        total_accuracy += forest.accuracy as f64;
+        forests.push(forest);
+
+        let mean: f64 = total_accuracy / forests.len() as f64;
+        if options
+            .target_stability
+            .is_some_and(|eps| forests.len() >= 2 && (mean - previous_mean).abs() < eps)
+        {
+            break;
+        }
+    }
+
+    Ok(forests)
+}
+
+/*
+    Calculates the share of the vote for an increase when every forest is weighted by its test accuracy. When
+    every forest has an accuracy of 0, which --no-invert allows on a small test set, the weights say nothing
+    and each forest counts alike instead
+
+    @param (weighted_increase: f64) sum of the accuracies of the forests predicting an increase
+    @param (total_weight: f64) sum of the accuracies of every forest
+    @param (num_inc: i32) number of forests predicting an increase
+    @param (forests: usize) number of forests
+
+    @return (f64) share of the vote for an increase, between 0 and 1
+*/
+pub fn weighted_share(
+    weighted_increase: f64,
+    total_weight: f64,
+    num_inc: i32,
+    forests: usize,
+) -> f64 {
+    if total_weight == 0.0 {
+        num_inc as f64 / forests as f64
+    } else {
+        weighted_increase / total_weight
+    }
+}
+
+/*
+    Lets trained forests vote on the prediction of a stock
+
+    @param (forests: &[TrainedForest]) forests trained by train_votes
+    @param (stock: &Stock) stock to predict
+    @param (features: &[usize]) indices of the features the forests were trained on
+
+    @return (ForestVote) number of votes for each direction, average accuracy and summed confusion matrix
+*/
+pub fn tally_votes(forests: &[TrainedForest], stock: &Stock, features: &[usize]) -> ForestVote {
+    let mut vote: ForestVote = ForestVote {
+        num_inc: 0,
+        num_dec: 0,
+        weighted_increase: 0.0,
+        probability: 0.0,
+        accuracy: 0.0,
+        raw_accuracy: 0.0,
+        confusion: [[0; 2]; 2],
+    };
+
+    let mut total_weight: f64 = 0.0;
+    for trained in forests {
+        let forest: ForestResult = trained.predict(stock, features);
+
+        total_weight += forest.accuracy as f64;
+        if Tomorrow::from_label(forest.prediction).is_some_and(|tomorrow| tomorrow.is_increase()) {
+            vote.num_inc += 1;
+            vote.weighted_increase += forest.accuracy as f64;
+        } else {
+            vote.num_dec += 1;
+        }
+
+        vote.probability += forest.probability;
+        vote.accuracy += forest.accuracy;
+        vote.raw_accuracy += if forest.inverted {
+            1.0 - forest.accuracy
+        } else {
+            forest.accuracy
+        };
+
+        for (actual, row) in forest.confusion.iter().enumerate() {
+            for (predicted, count) in row.iter().enumerate() {
+                vote.confusion[actual][predicted] += count;
+            }
+        }
+    }
+
+    vote.weighted_increase = weighted_share(
+        vote.weighted_increase,
+        total_weight,
+        vote.num_inc,
+        forests.len(),
+    );
+    vote.probability /= forests.len() as f64;
+    vote.accuracy /= forests.len() as f32;
+    vote.raw_accuracy /= forests.len() as f32;
+
+    vote
+}
+
+/*
+    Explains a prediction by replacing one feature at a time with its median over the training stocks and
+    measuring how much the average increase probability of the forests drops. A positive contribution means
+    the actual value of the feature pushed the forests toward an increase, a negative one toward a decrease
+
+    @param (forests: &[TrainedForest]) forests trained by train_votes
+    @param (stocks: &[Stock]) vector of Stock objects the forests were trained on
+    @param (features: &[usize]) indices of the features the forests were trained on
+    @param (predict_index: usize) index of the stock to explain, the medians are taken over the stocks before it
+
+    @return (Vec<(usize, f64)>) every feature index with its contribution, largest contribution first
+*/
+pub fn explain_prediction(
+    forests: &[TrainedForest],
+    stocks: &[Stock],
+    features: &[usize],
+    predict_index: usize,
+) -> Vec<(usize, f64)> {
+    let mean_probability = |values: &[f64]| {
+        forests
+            .iter()
+            .map(|forest| forest.increase_probability(values))
+            .sum::<f64>()
+            / forests.len() as f64
+    };
+
+    let values: Vec<f64> = stocks[predict_index].feature_subset(features);
+    let probability: f64 = mean_probability(&values);
+
+    let mut contributions: Vec<(usize, f64)> = features
+        .iter()
+        .enumerate()
+        .map(|(position, feature)| {
+            let history: Vec<f64> = stocks[0..predict_index]
+                .iter()
+                .map(|stock| stock.feature_subset(&[*feature])[0])
+                .collect();
+
+            let mut perturbed: Vec<f64> = values.clone();
+            perturbed[position] = calculate_percentile(&history, 50.0);
+
+            (*feature, probability - mean_probability(&perturbed))
+        })
+        .collect();
+    contributions.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+
+    contributions
+}
+
+/*
+    Trains random forests on different splits with train_votes and lets them vote on the prediction
+
+    @param (stocks: &[Stock]) vector of Stock objects parsed from the input file
+    @param (options: &ForestOptions) features to train on and whether to flip inaccurate forests
+    @param (predict_index: usize) index of the stock to predict, only the stocks before it are used for training
+    @param (rng: &mut impl Rng) random number generator of the splits and the forests
+
+    @return (CustomResult<ForestVote>) CustomResult containing the number of votes for each direction, average
+    accuracy and summed confusion matrix, or the error of building a training table
+*/
+pub fn vote_forest(
+    stocks: &[Stock],
+    options: &ForestOptions,
+    predict_index: usize,
+    rng: &mut impl Rng,
+) -> CustomResult<ForestVote> {
+    let forests: Vec<TrainedForest> = train_votes(stocks, options, predict_index, rng)?;
+
+    Ok(tally_votes(
+        &forests,
+        &stocks[predict_index],
+        &options.features,
+    ))
+}
+
+/*
+    Estimates the accuracy of a forest with k-fold cross-validation, every labeled stock before
+    predict_index is tested exactly once by a forest trained on the other folds
+
+    @param (stocks: &[Stock]) vector of Stock objects parsed from the input file
+    @param (options: &ForestOptions) features and hyperparameters of the forests
+    @param (predict_index: usize) index of the stock to predict, only the stocks before it are used
+    @param (folds: usize) number of folds
+    @param (rng: &mut impl Rng) random number generator of the folds and the forests
+
+    @return (CustomResult<Vec<f32>>) CustomResult containing the raw test accuracy of every fold that had both
+    training and test stocks, or the error of building the training table of a fold
+*/
+pub fn cross_validate(
+    stocks: &[Stock],
+    options: &ForestOptions,
+    predict_index: usize,
+    folds: usize,
+    rng: &mut impl Rng,
+) -> CustomResult<Vec<f32>> {
+    let dataset: &[Stock] = &stocks[0..predict_index];
+
+    let mut indices: Vec<usize> = (0..dataset.len()).collect();
+    indices.shuffle(rng);
+
+    let mut accuracies: Vec<f32> = Vec::new();
+
+    for fold in 0..folds {
+        let mut training_set: Vec<Stock> = Vec::new();
+        let mut test_set: Vec<Stock> = Vec::new();
+
+        for (position, idx) in indices.iter().enumerate() {
+            if position % folds == fold {
+                test_set.push(dataset[*idx].clone());
+            } else {
+                push_training_row(&mut training_set, dataset, *idx, options.recency_weight);
+            }
+        }
 
-    @return (f64, f32) the predicted result and accuracy respectively
+        if training_set.is_empty() || test_set.is_empty() {
+            continue;
+        }
+
+        check_training_set(&training_set)?;
+        let table_builder: TableBuilder = construct_table(&training_set, &options.features);
+        let table: Table = table_builder
+            .build()
+            .map_err(|e| format!("could not build the training table: {}", e))?;
+        let classifier: RandomForestClassifier = fit_forest(table, options, rng);
+
+        let num_correct: usize = test_set
+            .iter()
+            .filter(|stock| {
+                majority_label(&classifier, &stock.feature_subset(&options.features))
+                    == stock.get_label()
+            })
+            .count();
+
+        accuracies.push(num_correct as f32 / test_set.len() as f32);
+    }
+
+    Ok(accuracies)
+}
+
+/*
+    Replaces the volume feature of every stock with its bucket among the volumes of the trailing
+    VOLUME_BUCKET_WINDOW days, only looking back so training, testing and the final row are treated alike
+
+    @param (stocks: &mut [Stock]) vector of stock objects in date order
+    @param (buckets: usize) number of buckets, the highest volume of a window falls in bucket buckets - 1
+*/
+pub fn bucket_volumes(stocks: &mut [Stock], buckets: usize) {
+    let volumes: Vec<f64> = stocks.iter().map(|stock| stock.get_array()[5]).collect();
+
+    for (i, stock) in stocks.iter_mut().enumerate() {
+        let window: &[f64] = &volumes[(i + 1).saturating_sub(VOLUME_BUCKET_WINDOW)..=i];
+        let below: usize = window.iter().filter(|volume| **volume < volumes[i]).count();
+        let rank: f64 = below as f64 / window.len() as f64;
+
+        stock.set_volume_feature((rank * buckets as f64).floor());
+    }
+}
+
+/*
+    Replaces the volume feature of every stock with a transform of its volume. The rank is taken against the
+    volumes of the rows before train_end only, so the training, test and final rows are all ranked against
+    the same window and the rows after it do not leak into the features
+
+    @param (stocks: &mut [Stock]) vector of stock objects in date order
+    @param (transform: VolumeTransform) transform applied to every volume
+    @param (train_end: usize) number of leading rows whose volumes the rank is taken against
+*/
+pub fn transform_volumes(stocks: &mut [Stock], transform: VolumeTransform, train_end: usize) {
+    let mut window: Vec<u64> = stocks[..train_end]
+        .iter()
+        .map(|stock| stock.get_volume())
+        .collect();
+    window.sort_unstable();
+
+    for stock in stocks.iter_mut() {
+        let volume: u64 = stock.get_volume();
+        let value: f64 = match transform {
+            VolumeTransform::None => volume as f64,
+            VolumeTransform::Log => (volume as f64).ln_1p(),
+            VolumeTransform::Rank => {
+                window.partition_point(|other| *other < volume) as f64 / window.len().max(1) as f64
+            }
+        };
+
+        stock.set_volume_feature(value);
+    }
+}
+
+/*
+    Labels every stock with the direction of the price from its day to the next and sets its return, the
+    last stock keeps the Predict label
+
+    @param (stocks: &mut [Stock]) vector of stock objects in date order
+    @param (return_type: ReturnType) whether the returns are logarithmic or simple
 */
-pub fn run_forest(stocks: &[Stock]) -> (f64, f32) {
-    let ultimo: Stock = stocks[stocks.len() - 1].clone();
-    let dataset: Vec<Stock> = stocks[0..stocks.len() - 1].to_vec();
+pub fn label_stocks(stocks: &mut [Stock], return_type: ReturnType) {
+    for i in 0..stocks.len().saturating_sub(1) {
+        let tomorrow: Tomorrow = if stocks[i].get_price() <= stocks[i + 1].get_price() {
+            Tomorrow::Increase
+        } else {
+            Tomorrow::Decrease
+        };
+        stocks[i].set_tomorrow(tomorrow);
+
+        let curr_price: f64 = stocks[i].get_price();
+        stocks[i + 1].set_return(curr_price, return_type);
+    }
+}
 
-    let (training_set, test_set) = split_data(&dataset, 0.9);
+/*
+    Joins the series of several files into one, in the order given. Every file only adds its rows dated after
+    the last row of the series so far, so overlapping rows are taken from the earlier file. With
+    MergeMode::Chain the rows a file adds are scaled by ratio = p_prev / p_next, where p_prev is the price of
+    the last row so far and p_next the price of the file on the same date, so the return across the join is
+    the file's own. Without a row of that date p_next is the price of the first row added, which makes the
+    return across the join 0. This is the ratio back adjustment of a continuous futures contract, applied
+    forward so the first file keeps its prices
 
-    let table_builder: TableBuilder = construct_table(&training_set);
+    @param (series: &[(String, Vec<Stock>)]) name and stocks in date order of every file, at least one
+    @param (mode: MergeMode) whether the files are scaled to join without a jump
+    @param (return_type: ReturnType) whether the returns set when labelling again are logarithmic or simple
 
-    let table: Table = table_builder.build().unwrap();
+    @return (CustomResult<(Vec<Stock>, Vec<f64>)>) CustomResult containing the joined stocks, labeled again with
+    the last one to predict, and the ratio every file after the first was scaled by, or an error if a date
+    cannot be read or a file adds no rows
+*/
+pub fn merge_series(
+    series: &[(String, Vec<Stock>)],
+    mode: MergeMode,
+    return_type: ReturnType,
+) -> CustomResult<(Vec<Stock>, Vec<f64>)> {
+    let date_of = |stock: &Stock| {
+        stock
+            .get_date()
+            .map_err(|e| format!("could not read the date {}: {}", stock.get_date_string(), e))
+    };
 
-    let classifier: RandomForestClassifier = RandomForestClassifierOptions::new().fit(Gini, table);
+    let mut merged: Vec<Stock> = series[0].1.clone();
+    let mut ratios: Vec<f64> = Vec::new();
 
-    let num_tests: f32 = test_set.len() as f32;
-    let mut num_correct: f32 = 0.0;
+    for (name, stocks) in &series[1..] {
+        let last: &Stock = &merged[merged.len() - 1];
+        let last_date: NaiveDate = date_of(last)?;
 
-    for stock in test_set {
-        let result = classifier.predict(&stock.get_array());
+        let mut start: usize = stocks.len();
+        for (idx, stock) in stocks.iter().enumerate() {
+            if date_of(stock)? > last_date {
+                start = idx;
+                break;
+            }
+        }
+        if start == stocks.len() {
+            return Err(format!(
+                "{} has no rows after {}, the last date of the files before it",
+                name,
+                last.get_date_string()
+            )
+            .into());
+        }
+
+        let ratio: f64 = match mode {
+            MergeMode::Concat => 1.0,
+            MergeMode::Chain => {
+                let boundary: &Stock = match start.checked_sub(1) {
+                    Some(idx) if date_of(&stocks[idx])? == last_date => &stocks[idx],
+                    _ => &stocks[start],
+                };
+                last.get_price() / boundary.get_price()
+            }
+        };
+        ratios.push(ratio);
 
-        if result == stock.get_label() {
-            num_correct += 1.0;
+        for stock in &stocks[start..] {
+            let mut stock: Stock = stock.clone();
+            stock.scale(ratio);
+            merged.push(stock);
         }
     }
 
-    let mut accuracy = num_correct / num_tests;
-    let mut switch_flag: bool = false;
+    label_stocks(&mut merged, return_type);
+    let last: usize = merged.len() - 1;
+    merged[last].set_tomorrow(Tomorrow::Predict);
+
+    Ok((merged, ratios))
+}
+
+/*
+    Finds the days on which the close moved differently from the adjusted close, which happens when a split
+    or dividend is only reflected in the adjusted close
+
+    @param (stocks: &[Stock]) vector of stock objects in date order
+    @param (threshold: f64) largest accepted relative difference between the close-to-close and the
+    adj_close-to-adj_close ratio, e.g. 0.2 for 20%
+
+    @return (Vec<usize>) indices of the stocks whose ratio to the previous day differs by more than threshold
+*/
+pub fn detect_splits(stocks: &[Stock], threshold: f64) -> Vec<usize> {
+    (1..stocks.len())
+        .filter(|idx| {
+            let close_ratio: f64 = stocks[*idx].get_close() / stocks[idx - 1].get_close();
+            let adj_ratio: f64 = stocks[*idx].get_adj_close() / stocks[idx - 1].get_adj_close();
+
+            (close_ratio / adj_ratio - 1.0).abs() > threshold
+        })
+        .collect()
+}
+
+/*
+    Finds runs of consecutive rows with identical open, high, low and close, the usual sign of a halted or
+    broken data feed which would otherwise give a silent zero volatility forecast
+
+    @param (stocks: &[Stock]) vector of stock objects in date order
+    @param (min_length: usize) shortest run of identical rows reported
+
+    @return (Vec<(usize, usize)>) first and last index of every run of at least min_length identical rows
+*/
+pub fn find_stale_runs(stocks: &[Stock], min_length: usize) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+
+    let mut first: usize = 0;
+    for idx in 1..=stocks.len() {
+        let unchanged: bool =
+            idx < stocks.len() && stocks[idx].get_array()[0..5] == stocks[first].get_array()[0..5];
+        if unchanged {
+            continue;
+        }
 
-    // if the accuracy is less than 50% it is actually useful to do the opposite of what the model says
-    if accuracy < 0.5 {
-        accuracy = 1.0 - accuracy;
-        switch_flag = true;
+        if idx - first >= min_length {
+            runs.push((first, idx - 1));
+        }
+        first = idx;
     }
 
-    let mut result = classifier.predict(&ultimo.get_array());
+    runs
+}
+
+/*
+    Computes the engineered features of every stock with enough history: the ratio of the short to the long
+    simple moving average of the close, the relative strength index of the close and the day of the week.
+    Only closes up to and including the current day are used, so tomorrow's label is not leaked
+
+    @param (stocks: &[Stock]) vector of stock structs in date order
+
+    @return (ParseResult<Vec<Stock>>) copies of the stocks with their engineered features set, the first
+    SMA_LONG_WINDOW - 1 stocks are skipped, or the error of a date which could not be parsed
+*/
+pub fn engineer_features(stocks: &[Stock]) -> ParseResult<Vec<Stock>> {
+    let history: usize = (SMA_LONG_WINDOW - 1).max(RSI_WINDOW);
+    let closes: Vec<f64> = stocks.iter().map(|stock| stock.get_close()).collect();
+    let mut engineered: Vec<Stock> = Vec::new();
+
+    for (idx, stock) in stocks.iter().enumerate().skip(history) {
+        let short_sma: f64 =
+            closes[idx + 1 - SMA_SHORT_WINDOW..=idx].iter().sum::<f64>() / SMA_SHORT_WINDOW as f64;
+        let long_sma: f64 =
+            closes[idx + 1 - SMA_LONG_WINDOW..=idx].iter().sum::<f64>() / SMA_LONG_WINDOW as f64;
+
+        let (gain, loss): (f64, f64) = closes[idx - RSI_WINDOW..=idx]
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .fold((0.0, 0.0), |(gain, loss), change| {
+                (gain + change.max(0.0), loss - change.min(0.0))
+            });
+        let rsi: f64 = if loss == 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        };
+
+        let date: NaiveDate = stock.get_date()?;
+        let day_of_week: f64 = date.weekday().num_days_from_monday() as f64;
+
+        let mut stock: Stock = stock.clone();
+        stock.set_engineered_features([short_sma / long_sma, rsi, day_of_week]);
+        engineered.push(stock);
+    }
+
+    Ok(engineered)
+}
+
+/*
+    Calculates the daily return series, ln(price today / price yesterday) or price today / price yesterday - 1,
+    from the prices of the stocks. These are the same values run stores on each stock with set_return and
+    calculate_drift reads back with get_return, except that the first stock has no previous price and so no
+    entry here
+
+    @param (stocks: &[Stock]) vector of stock objects in date order
+    @param (return_type: ReturnType) whether the returns are logarithmic or simple
+
+    @return (Vec<f64>) one return per stock after the first
+*/
+pub fn calculate_returns(stocks: &[Stock], return_type: ReturnType) -> Vec<f64> {
+    stocks
+        .windows(2)
+        .map(|pair| return_type.calculate(pair[0].get_price(), pair[1].get_price()))
+        .collect()
+}
+
+/*
+    Calculates the daily log return series, ln(price today / price yesterday), from the prices of the stocks,
+    see calculate_returns
+
+    @param (stocks: &[Stock]) vector of stock objects in date order
+
+    @return (Vec<f64>) one log return per stock after the first
+*/
+pub fn log_returns(stocks: &[Stock]) -> Vec<f64> {
+    calculate_returns(stocks, ReturnType::Log)
+}
+
+/*
+    Calculates the lag-1 autocorrelation of a series, the correlation of every value with the next one. The
+    Monte Carlo simulation assumes independent returns, for which it is close to 0
+
+    @param (values: &[f64]) series such as the one returned by calculate_returns
+
+    @return (f64) autocorrelation between -1 and 1, NaN for fewer than two values or a constant series
+*/
+pub fn lag1_autocorrelation(values: &[f64]) -> f64 {
+    let mean: f64 = values.iter().sum::<f64>() / values.len() as f64;
+
+    let variance: f64 = values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>();
+    let covariance: f64 = values
+        .windows(2)
+        .map(|pair| (pair[0] - mean) * (pair[1] - mean))
+        .sum::<f64>();
+
+    covariance / variance
+}
+
+/*
+    Joins a stock and a benchmark such as an index on their dates and takes the returns of both between
+    consecutive shared dates, so missing rows in either series do not misalign the returns
+
+    @param (stocks: &[Stock]) vector of stock objects in date order
+    @param (benchmark: &[Stock]) vector of benchmark stock objects in date order
+    @param (return_type: ReturnType) whether the returns are logarithmic or simple
+
+    @return (Vec<(String, f64, f64)>) date of the later day, return of the stock and return of the benchmark
+*/
+fn aligned_returns(
+    stocks: &[Stock],
+    benchmark: &[Stock],
+    return_type: ReturnType,
+) -> Vec<(String, f64, f64)> {
+    let benchmark_prices: HashMap<String, f64> = benchmark
+        .iter()
+        .map(|stock| (stock.get_date_string(), stock.get_price()))
+        .collect();
+    let aligned: Vec<(String, f64, f64)> = stocks
+        .iter()
+        .filter_map(|stock| {
+            benchmark_prices
+                .get(&stock.get_date_string())
+                .map(|benchmark_price| {
+                    (stock.get_date_string(), stock.get_price(), *benchmark_price)
+                })
+        })
+        .collect();
+
+    aligned
+        .windows(2)
+        .map(|pair| {
+            (
+                pair[1].0.clone(),
+                return_type.calculate(pair[0].1, pair[1].1),
+                return_type.calculate(pair[0].2, pair[1].2),
+            )
+        })
+        .collect()
+}
+
+/*
+    Calculates the beta and alpha of a stock against a benchmark such as an index, from the returns between
+    the dates the two series share
+
+    @param (stocks: &[Stock]) vector of stock objects in date order
+    @param (benchmark: &[Stock]) vector of benchmark stock objects in date order
+    @param (return_type: ReturnType) whether the returns are logarithmic or simple
+
+    @return (CustomResult<(f64, f64)>) CustomResult containing the beta, the covariance of the returns over the
+    variance of the benchmark returns, and the alpha, the mean return per period not explained by the benchmark
+*/
+pub fn calculate_beta(
+    stocks: &[Stock],
+    benchmark: &[Stock],
+    return_type: ReturnType,
+) -> CustomResult<(f64, f64)> {
+    let returns: Vec<(String, f64, f64)> = aligned_returns(stocks, benchmark, return_type);
+    if returns.len() < 2 {
+        return Err(format!(
+            "the benchmark shares {} returns with the data, at least 2 are needed",
+            returns.len()
+        )
+        .into());
+    }
+
+    regress_returns(&returns)
+        .ok_or_else(|| "the benchmark price never changes on the shared dates".into())
+}
+
+/*
+    Calculates beta over a window sliding one shared date at a time, to show how the sensitivity of a stock
+    to its benchmark changes
+
+    @param (stocks: &[Stock]) vector of stock objects in date order
+    @param (benchmark: &[Stock]) vector of benchmark stock objects in date order
+    @param (window: usize) number of returns in each window, at least 2
+    @param (return_type: ReturnType) whether the returns are logarithmic or simple
+
+    @return (CustomResult<Vec<(String, f64)>>) CustomResult containing the last date and the beta of every
+    window, NaN for a window where the benchmark never moves, or an error if there are fewer returns than
+    the window
+*/
+pub fn calculate_rolling_beta(
+    stocks: &[Stock],
+    benchmark: &[Stock],
+    window: usize,
+    return_type: ReturnType,
+) -> CustomResult<Vec<(String, f64)>> {
+    let returns: Vec<(String, f64, f64)> = aligned_returns(stocks, benchmark, return_type);
+    if returns.len() < window {
+        return Err(format!(
+            "the benchmark shares {} returns with the data, fewer than the rolling window of {}",
+            returns.len(),
+            window
+        )
+        .into());
+    }
+
+    Ok(returns
+        .windows(window)
+        .map(|slice| {
+            let beta: f64 = regress_returns(slice).map_or(f64::NAN, |(beta, _)| beta);
+            (slice[window - 1].0.clone(), beta)
+        })
+        .collect())
+}
+
+/*
+    Regresses the returns of a stock on the returns of its benchmark
+
+    @param (returns: &[(String, f64, f64)]) returns joined by aligned_returns
+
+    @return (Option<(f64, f64)>) beta and alpha, None if the benchmark returns do not vary
+*/
+fn regress_returns(returns: &[(String, f64, f64)]) -> Option<(f64, f64)> {
+    let count: f64 = returns.len() as f64;
+    let stock_mean: f64 = returns.iter().map(|pair| pair.1).sum::<f64>() / count;
+    let benchmark_mean: f64 = returns.iter().map(|pair| pair.2).sum::<f64>() / count;
 
-    if switch_flag {
-        result = if result == 1.0 { 0.0 } else { 1.0 };
+    let covariance: f64 = returns
+        .iter()
+        .map(|pair| (pair.1 - stock_mean) * (pair.2 - benchmark_mean))
+        .sum::<f64>();
+    let benchmark_variance: f64 = returns
+        .iter()
+        .map(|pair| (pair.2 - benchmark_mean).powi(2))
+        .sum::<f64>();
+    if benchmark_variance == 0.0 {
+        return None;
     }
 
-    (result, accuracy)
+    let beta: f64 = covariance / benchmark_variance;
+
+    Some((beta, stock_mean - beta * benchmark_mean))
 }
 
 /*
-    Calculates the drift for Brownian motion
+    Calculates the drift for Brownian motion. With a risk free rate the historical mean return is replaced
+    by that rate, which gives risk-neutral price paths whose mean is the forward price rather than the
+    expected future price. Geometric Brownian motion models log returns, so the drift and variance are exact
+    for the default --return-type log and only approximate for simple returns
 
-    @param (stocks: &Vec<Stock>) vector of stock objects
+    @param (stocks: &[Stock]) vector of stock objects
+    @param (risk_free_rate: Option<f64>) continuously compounded risk free rate per period, if risk-neutral
 
     @return (f64, f64, f64) the calculated drift and variance respectively
 */
-pub fn calculate_drift(stocks: &Vec<Stock>) -> (f64, f64) {
+pub fn calculate_drift(stocks: &[Stock], risk_free_rate: Option<f64>) -> (f64, f64) {
     let mut mean = 0.0;
 
     for stock in stocks {
@@ -122,56 +1346,241 @@ pub fn calculate_drift(stocks: &Vec<Stock>) -> (f64, f64) {
 
     var /= stocks.len() as f64;
 
+    if let Some(rate) = risk_free_rate {
+        mean = rate;
+    }
+
     (mean - (0.5 * var), var)
 }
 
-/*
-    Calculate the daily returns matrix which uses logarithmic daily returns to find the change in a specific stock
+#[derive(Debug, Clone, Default)]
+pub struct RunningStats {
+    count: usize,
+    mean: f64,
+    // sum of the squared differences from the mean, Welford's M2
+    squared_diffs: f64,
+}
 
-    @param (stocks: &Vec<Stock>) vector of stock objects
+impl RunningStats {
+    /*
+        Creates empty statistics which returns are pushed to one at a time
 
-    @return (Vec<Vec<f64>>) daily return matrix with the coefficients to be used in Black-Scholes
-*/
-pub fn calculate_daily_returns(stocks: &Vec<Stock>) -> Vec<Vec<f64>> {
-    let (drift, var) = calculate_drift(stocks);
+        @return (RunningStats) statistics of no returns
+    */
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /*
+        Adds one logarithmic return with Welford's algorithm, in constant time and without keeping the history
+
+        @param (&mut self) current statistics
+        @param (log_return: f64) logarithmic return of the newest bar
+    */
+    pub fn push(&mut self, log_return: f64) {
+        self.count += 1;
+        let delta: f64 = log_return - self.mean;
+        self.mean += delta / self.count as f64;
+        self.squared_diffs += delta * (log_return - self.mean);
+    }
+
+    /*
+        Calculates the drift and variance of the returns pushed so far, the same values calculate_drift
+        returns for stocks with these returns and no risk free rate
+
+        @param (&self) current statistics
+
+        @return (f64, f64) the calculated drift and variance respectively, NaN before the first push
+    */
+    pub fn drift(&self) -> (f64, f64) {
+        let var: f64 = self.squared_diffs / self.count as f64;
+
+        (self.mean - (0.5 * var), var)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationOptions {
+    // number of simulated days, the first of which is the current price
+    pub days: usize,
+    // number of random walks
+    pub trials: usize,
+    // continuously compounded risk free rate per period, if the simulation is risk-neutral
+    pub risk_free_rate: Option<f64>,
+    // resample the historical log returns instead of drawing normally distributed ones
+    pub bootstrap: bool,
+    // hypothetical price the random walks start from instead of the last price, for what-if scenarios
+    pub start_price: Option<f64>,
+}
+
+impl SimulationOptions {
+    /*
+        Returns the price every random walk starts from
+
+        @param (&self) current simulation options
+        @param (stocks: &[Stock]) vector of stock objects in date order
+
+        @return (f64) the scenario start price if one is set, otherwise the price of the last stock
+    */
+    pub fn start_price(&self, stocks: &[Stock]) -> f64 {
+        self.start_price
+            .unwrap_or_else(|| stocks[stocks.len() - 1].get_price())
+    }
+}
+
+// draws the daily growth factors of the random walks
+enum ReturnSampler {
+    Normal {
+        drift: f64,
+        std: f64,
+        normal: Normal,
+    },
+    Bootstrap(Vec<f64>),
+}
+
+impl ReturnSampler {
+    /*
+        Chooses how the daily growth factors are drawn, bootstrapping needs at least one historical return
+        and falls back to normal returns otherwise
 
-    let std: f64 = var.sqrt();
+        @param (stocks: &[Stock]) vector of stock objects
+        @param (options: &SimulationOptions) options of the simulation
 
-    let days = 30;
-    let trials = 50000;
+        @return (CustomResult<ReturnSampler>) CustomResult containing the sampler of the daily growth factors,
+        or an error if the drift or volatility estimated from the stocks is not a usable number
+    */
+    fn new(stocks: &[Stock], options: &SimulationOptions) -> CustomResult<Self> {
+        let (drift, var) = calculate_drift(stocks, options.risk_free_rate);
+        if !drift.is_finite() {
+            return Err(format!("the estimated drift {} is not a finite number", drift).into());
+        }
+
+        // the first stock has no previous price, so it has no return
+        let mut returns: Vec<f64> = stocks
+            .iter()
+            .skip(1)
+            .map(|stock| stock.get_return())
+            .collect();
+
+        if !options.bootstrap || returns.is_empty() {
+            return Ok(ReturnSampler::Normal {
+                drift,
+                std: checked_std(var.sqrt())?,
+                normal: Normal::new(0.0, 1.0)?,
+            });
+        }
 
-    let mut rng = rand::thread_rng();
+        // risk-neutral bootstrapping keeps the shape of the returns but moves their mean to the drift
+        if options.risk_free_rate.is_some() {
+            let mean: f64 = returns.iter().sum::<f64>() / returns.len() as f64;
+            for value in returns.iter_mut() {
+                *value += drift - mean;
+            }
+        }
+
+        Ok(ReturnSampler::Bootstrap(returns))
+    }
 
-    let normal = Normal::new(0.0, 1.0).unwrap();
+    /*
+        Draws the growth factor of one day of one random walk
 
-    let mut daily_returns: Vec<Vec<f64>> = Vec::new();
+        @param (&self) current sampler
+        @param (rng: &mut impl Rng) random number generator
 
-    for _ in 0..days {
-        let mut z: Vec<f64> = Vec::new();
-        for _ in 0..trials {
-            z.push((drift + std * normal.sample(&mut rng)).exp());
+        @return (f64) factor the price is multiplied by
+    */
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match self {
+            ReturnSampler::Normal { drift, std, normal } => {
+                (drift + std * normal.sample(rng)).exp()
+            }
+            ReturnSampler::Bootstrap(returns) => returns[rng.gen_range(0..returns.len())].exp(),
         }
-        daily_returns.push(z);
     }
+}
+
+/*
+    Guards the standard deviation of a distribution estimated from the data, which is NaN when the data has
+    too few rows or non-positive prices
+
+    @param (std: f64) standard deviation to check
+
+    @return (CustomResult<f64>) CustomResult containing std if it is finite and non-negative
+*/
+fn checked_std(std: f64) -> CustomResult<f64> {
+    if std.is_finite() && std >= 0.0 {
+        Ok(std)
+    } else {
+        Err(format!(
+            "the estimated volatility {} is not a finite non-negative number",
+            std
+        )
+        .into())
+    }
+}
+
+/*
+    Calculate the daily returns matrix which uses logarithmic daily returns to find the change in a specific stock
+
+    @param (stocks: &[Stock]) vector of stock objects
+    @param (options: &SimulationOptions) number of days, drift and return distribution of the simulation
+    @param (rng: &mut impl Rng) random number generator the generator of every day is seeded from
+
+    @return (CustomResult<Vec<Vec<f64>>>) CustomResult containing the daily return matrix with the coefficients
+    to be used in Black-Scholes, or an error if the run was interrupted with Ctrl-C
+*/
+pub fn calculate_daily_returns(
+    stocks: &[Stock],
+    options: &SimulationOptions,
+    rng: &mut impl Rng,
+) -> CustomResult<Vec<Vec<f64>>> {
+    let sampler: ReturnSampler = ReturnSampler::new(stocks, options)?;
 
-    daily_returns
+    // the days are drawn in parallel, each with its own generator seeded in order so the thread count does not
+    // change the draws
+    let seeds: Vec<u64> = (0..options.days).map(|_| rng.gen()).collect();
+    // a day started after an interrupt is skipped, which makes the collect below None
+    let daily_returns: Option<Vec<Vec<f64>>> = seeds
+        .into_par_iter()
+        .map(|seed| {
+            if crate::interrupted() {
+                return None;
+            }
+
+            let mut rng: StdRng = StdRng::seed_from_u64(seed);
+            Some(
+                (0..options.trials)
+                    .map(|_| sampler.sample(&mut rng))
+                    .collect(),
+            )
+        })
+        .collect();
+
+    daily_returns.ok_or_else(|| "interrupted during the Monte Carlo simulation".into())
 }
 
 /*
     Calculate the price paths (random walks predicting prices) for the Monte Carlo trials
 
-    @param (stocks: &Vec<Stock>) vector of stock object
+    @param (stocks: &[Stock]) vector of stock object
+    @param (options: &SimulationOptions) number of days, drift and return distribution of the simulation
+    @param (rng: &mut impl Rng) random number generator of the daily returns
 
-    @return (Vec<Vec<f64>>) vector where the columns are individual random walks
+    @return (CustomResult<Vec<Vec<f64>>>) CustomResult containing a vector where the columns are individual
+    random walks
 */
-pub fn calculate_price_paths(stocks: &Vec<Stock>) -> Vec<Vec<f64>> {
-    let daily_returns = calculate_daily_returns(stocks);
+pub fn calculate_price_paths(
+    stocks: &[Stock],
+    options: &SimulationOptions,
+    rng: &mut impl Rng,
+) -> CustomResult<Vec<Vec<f64>>> {
+    let daily_returns = calculate_daily_returns(stocks, options, rng)?;
 
     let mut price_paths: Vec<Vec<f64>> = Vec::new();
 
     let mut first_day: Vec<f64> = Vec::new();
     for _ in 0..daily_returns[0].len() {
-        first_day.push(stocks[stocks.len() - 1].get_price());
+        first_day.push(options.start_price(stocks));
     }
     price_paths.push(first_day);
 
@@ -183,5 +1592,189 @@ pub fn calculate_price_paths(stocks: &Vec<Stock>) -> Vec<Vec<f64>> {
         price_paths.push(price_path);
     }
 
-    price_paths
+    Ok(price_paths)
+}
+
+/*
+    Returns the simulated prices of the last day, the endpoint of every random walk, which every summary
+    of the Monte Carlo forecast is calculated from
+
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths
+
+    @return (&[f64]) final day price of every random walk
+*/
+pub fn final_prices(price_paths: &[Vec<f64>]) -> &[f64] {
+    &price_paths[price_paths.len() - 1]
+}
+
+/*
+    Calculates only the final day prices of the Monte Carlo trials, simulating the random walks in chunks
+    so the full days x trials matrix is never held in memory. The chunks run in parallel, so peak memory is
+    one f64 per trial for the final prices plus one f64 per trial in a chunk for every thread, independent of
+    the number of days
+
+    @param (stocks: &[Stock]) vector of stock objects
+    @param (options: &SimulationOptions) number of days, drift and return distribution of the simulation
+    @param (chunk_size: usize) number of random walks simulated at the same time
+    @param (rng: &mut impl Rng) random number generator the generator of every chunk is seeded from
+
+    @return (CustomResult<Vec<f64>>) CustomResult containing the final day price of every random walk
+*/
+pub fn calculate_final_prices(
+    stocks: &[Stock],
+    options: &SimulationOptions,
+    chunk_size: usize,
+    rng: &mut impl Rng,
+) -> CustomResult<Vec<f64>> {
+    let sampler: ReturnSampler = ReturnSampler::new(stocks, options)?;
+
+    let start_price: f64 = options.start_price(stocks);
+
+    let chunks: Vec<(usize, u64)> = (0..options.trials)
+        .step_by(chunk_size)
+        .map(|first| (chunk_size.min(options.trials - first), rng.gen()))
+        .collect();
+
+    // a chunk started after an interrupt is skipped, which makes the collect below None
+    let final_prices: Option<Vec<Vec<f64>>> = chunks
+        .into_par_iter()
+        .map(|(chunk, seed)| {
+            if crate::interrupted() {
+                return None;
+            }
+
+            let mut rng: StdRng = StdRng::seed_from_u64(seed);
+            let mut prices: Vec<f64> = vec![start_price; chunk];
+            for _ in 1..options.days {
+                for price in prices.iter_mut() {
+                    *price *= sampler.sample(&mut rng);
+                }
+            }
+
+            Some(prices)
+        })
+        .collect();
+
+    match final_prices {
+        Some(chunks) => Ok(chunks.concat()),
+        None => Err("interrupted during the Monte Carlo simulation".into()),
+    }
+}
+
+/*
+    Collapses the forecasts at several horizons into one present value: the mean simulated price at every
+    horizon h is discounted by exp(-rate * h) and the discounted prices are averaged with equal weights,
+    PV = 1/H * sum over h of exp(-rate * h) * mean(price_h)
+
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths, at least as many days
+    long as the longest horizon
+    @param (horizons: &[usize]) numbers of days ahead
+    @param (rate: f64) continuously compounded discount rate per period
+
+    @return (f64) discounted expected price
+*/
+pub fn discounted_expected_price(price_paths: &[Vec<f64>], horizons: &[usize], rate: f64) -> f64 {
+    let discounted: f64 = horizons
+        .iter()
+        .map(|horizon| {
+            let prices: &[f64] = &price_paths[*horizon];
+            let mean: f64 = prices.iter().sum::<f64>() / prices.len() as f64;
+            (-rate * *horizon as f64).exp() * mean
+        })
+        .sum::<f64>();
+
+    discounted / horizons.len() as f64
+}
+
+/*
+    Calculates the annualized volatility implied by the simulated prices at a horizon: the standard deviation
+    of the log returns from day 0 to the horizon over all paths, scaled by sqrt(periods_per_year / horizon).
+    Under geometric Brownian motion it is the same at every horizon
+
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths, at least as many days
+    long as the horizon
+    @param (horizon: usize) number of days ahead, at least 1
+    @param (periods_per_year: f64) number of data periods in one calendar year
+
+    @return (f64) annualized volatility, e.g. 0.3 for 30% a year
+*/
+pub fn implied_volatility(price_paths: &[Vec<f64>], horizon: usize, periods_per_year: f64) -> f64 {
+    let returns: Vec<f64> = price_paths[0]
+        .iter()
+        .zip(&price_paths[horizon])
+        .map(|(start, end)| (end / start).ln())
+        .collect();
+
+    let mean: f64 = returns.iter().sum::<f64>() / returns.len() as f64;
+    let variance: f64 =
+        returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+
+    (variance * periods_per_year / horizon as f64).sqrt()
+}
+
+/*
+    Finds the first day every simulated path reaches a target price, from above when the target is below the
+    current price and from below otherwise
+
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths, day 0 is the current price
+    @param (target: f64) price the paths have to reach
+
+    @return (Vec<Option<usize>>) first day each path is at or past the target, None if it never gets there
+*/
+pub fn first_passage_days(price_paths: &[Vec<f64>], target: f64) -> Vec<Option<usize>> {
+    (0..price_paths[0].len())
+        .map(|path| {
+            let rising: bool = target >= price_paths[0][path];
+            price_paths.iter().position(|prices| {
+                if rising {
+                    prices[path] >= target
+                } else {
+                    prices[path] <= target
+                }
+            })
+        })
+        .collect()
+}
+
+/*
+    Calculates the fraction of the simulated prices strictly above a threshold
+
+    @param (values: &[f64]) values such as the simulated final day prices
+    @param (threshold: f64) price to compare with, such as a strike
+
+    @return (f64) fraction between 0 and 1 of the values above the threshold
+*/
+pub fn fraction_above(values: &[f64], threshold: f64) -> f64 {
+    values.iter().filter(|value| **value > threshold).count() as f64 / values.len() as f64
+}
+
+/*
+    Calculates the fraction of the simulated prices strictly below a threshold
+
+    @param (values: &[f64]) values such as the simulated final day prices
+    @param (threshold: f64) price to compare with, such as a strike
+
+    @return (f64) fraction between 0 and 1 of the values below the threshold
+*/
+pub fn fraction_below(values: &[f64], threshold: f64) -> f64 {
+    values.iter().filter(|value| **value < threshold).count() as f64 / values.len() as f64
+}
+
+/*
+    Calculates a percentile of a set of values, interpolating linearly between the closest ranks
+
+    @param (values: &[f64]) values such as the simulated final day prices
+    @param (percentile: f64) percentile to calculate between 0 and 100
+
+    @return (f64) value below which the given percentage of values fall
+*/
+pub fn calculate_percentile(values: &[f64], percentile: f64) -> f64 {
+    let mut sorted: Vec<f64> = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let rank: f64 = (percentile / 100.0) * (sorted.len() - 1) as f64;
+    let lower: usize = rank.floor() as usize;
+    let upper: usize = rank.ceil() as usize;
+
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
 }
@@ -0,0 +1,123 @@
+use std::collections::BTreeSet;
+use std::fs;
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+use crate::stock::parse_date;
+use crate::CustomResult;
+
+// the days a market trades: every weekday that is not a holiday
+#[derive(Debug, Clone, Default)]
+pub struct TradingCalendar {
+    holidays: BTreeSet<NaiveDate>,
+}
+
+impl TradingCalendar {
+    /*
+        Creates a calendar which skips weekends and the given holidays
+
+        @param (holidays: &[NaiveDate]) dates the market is closed on besides weekends
+
+        @return (TradingCalendar) the calendar
+    */
+    pub fn new(holidays: &[NaiveDate]) -> TradingCalendar {
+        TradingCalendar {
+            holidays: holidays.iter().copied().collect(),
+        }
+    }
+
+    /*
+        Reads the holidays of a calendar from a file with one YYYY-MM-DD date per line, blank lines and lines
+        starting with # are skipped
+
+        @param (path: &str) path of the holiday file
+
+        @return (CustomResult<TradingCalendar>) CustomResult containing the calendar, or the error of reading
+        the file or of a line which is not a date
+    */
+    pub fn load(path: &str) -> CustomResult<TradingCalendar> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|e| format!("could not read the holidays {}: {}", path, e))?;
+
+        let mut holidays: Vec<NaiveDate> = Vec::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            holidays.push(parse_date(line).map_err(|e| {
+                format!(
+                    "{} line {}: invalid date {}: {}",
+                    path,
+                    line_number + 1,
+                    line,
+                    e
+                )
+            })?);
+        }
+
+        Ok(TradingCalendar::new(&holidays))
+    }
+
+    /*
+        Simple getter for the holidays of the calendar
+
+        @param (&self) current calendar
+
+        @return (Vec<NaiveDate>) the holidays in date order, without the weekends
+    */
+    pub fn get_holidays(&self) -> Vec<NaiveDate> {
+        self.holidays.iter().copied().collect()
+    }
+
+    /*
+        Checks if the market trades on a date
+
+        @param (&self) current calendar
+        @param (date: NaiveDate) date to check
+
+        @return (bool) true for a weekday which is not a holiday
+    */
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !self.holidays.contains(&date)
+    }
+
+    /*
+        Counts the trading days after one date up to and including another
+
+        @param (&self) current calendar
+        @param (from: NaiveDate) first date, not counted
+        @param (to: NaiveDate) last date, counted if it is a trading day
+
+        @return (usize) number of trading days, 0 if to is not after from
+    */
+    pub fn trading_days_between(&self, from: NaiveDate, to: NaiveDate) -> usize {
+        from.iter_days()
+            .skip(1)
+            .take_while(|date| *date <= to)
+            .filter(|date| self.is_trading_day(*date))
+            .count()
+    }
+
+    /*
+        Finds the date a number of trading days after a date, the inverse of trading_days_between
+
+        @param (&self) current calendar
+        @param (from: NaiveDate) starting date
+        @param (days: usize) number of trading days to move forward
+
+        @return (NaiveDate) the trading day reached, from itself when days is 0
+    */
+    pub fn add_trading_days(&self, from: NaiveDate, days: usize) -> NaiveDate {
+        let mut date: NaiveDate = from;
+        let mut remaining: usize = days;
+        while remaining > 0 {
+            date += Duration::days(1);
+            if self.is_trading_day(date) {
+                remaining -= 1;
+            }
+        }
+
+        date
+    }
+}
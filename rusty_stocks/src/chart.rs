@@ -0,0 +1,119 @@
+use chrono::{Duration, NaiveDate};
+use plotters::prelude::*;
+
+use crate::calculations::calculate_percentile;
+use crate::calendar::TradingCalendar;
+use crate::stock::Stock;
+use crate::CustomResult;
+
+/*
+    Renders the historical close prices followed by the median simulated price and the band between the 5th
+    and 95th percentiles of the simulated prices as a PNG chart
+
+    @param (path: &str) path of the PNG file, it is overwritten if it exists
+    @param (stocks: &[Stock]) vector of stock objects in date order
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths, day 0 is the current price
+    @param (periods_per_year: f64) number of data periods in one calendar year, used to date the simulated days
+    @param (calendar: Option<&TradingCalendar>) trading days the simulated days fall on for daily data, None to
+    spread them over calendar days with periods_per_year
+
+    @return (CustomResult<()>) error of reading a date or drawing the chart, if any
+*/
+pub fn plot_forecast(
+    path: &str,
+    stocks: &[Stock],
+    price_paths: &[Vec<f64>],
+    periods_per_year: f64,
+    calendar: Option<&TradingCalendar>,
+) -> CustomResult<()> {
+    let history: Vec<(NaiveDate, f64)> = stocks
+        .iter()
+        .map(|stock| stock.get_date().map(|date| (date, stock.get_close())))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("could not read a date to plot: {}", e))?;
+    let last_date: NaiveDate = history[history.len() - 1].0;
+
+    // inverse of calculate_horizon
+    let forecast_date = |day: usize| match calendar {
+        Some(calendar) => calendar.add_trading_days(last_date, day),
+        None => last_date + Duration::days((day as f64 * 365.0 / periods_per_year).round() as i64),
+    };
+    let band: Vec<(NaiveDate, f64, f64, f64)> = price_paths
+        .iter()
+        .enumerate()
+        .map(|(day, prices)| {
+            (
+                forecast_date(day),
+                calculate_percentile(prices, 5.0),
+                calculate_percentile(prices, 50.0),
+                calculate_percentile(prices, 95.0),
+            )
+        })
+        .collect();
+
+    let lowest: f64 = history
+        .iter()
+        .map(|point| point.1)
+        .chain(band.iter().map(|day| day.1))
+        .fold(f64::INFINITY, f64::min);
+    let highest: f64 = history
+        .iter()
+        .map(|point| point.1)
+        .chain(band.iter().map(|day| day.3))
+        .fold(f64::NEG_INFINITY, f64::max);
+    let margin: f64 = (highest - lowest) * 0.05;
+
+    let root = BitMapBackend::new(path, (1000, 600)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Price Forecast", ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(
+            history[0].0..band[band.len() - 1].0,
+            (lowest - margin)..(highest + margin),
+        )?;
+
+    chart
+        .configure_mesh()
+        .x_desc("Date")
+        .y_desc("Price")
+        .x_label_formatter(&|date| date.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    let band_outline: Vec<(NaiveDate, f64)> = band
+        .iter()
+        .map(|day| (day.0, day.3))
+        .chain(band.iter().rev().map(|day| (day.0, day.1)))
+        .collect();
+    chart
+        .draw_series(std::iter::once(Polygon::new(band_outline, BLUE.mix(0.2))))?
+        .label("5th to 95th percentile")
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], BLUE.mix(0.2).filled()));
+
+    chart
+        .draw_series(LineSeries::new(history, &BLACK))?
+        .label("Close")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+
+    chart
+        .draw_series(LineSeries::new(
+            band.iter().map(|day| (day.0, day.2)),
+            &BLUE,
+        ))?
+        .label("Median forecast")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+
+    chart
+        .configure_series_labels()
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .position(SeriesLabelPosition::UpperLeft)
+        .draw()?;
+
+    root.present()?;
+
+    Ok(())
+}
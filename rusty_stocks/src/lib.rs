@@ -1,153 +1,3242 @@
 use std::{
+    env,
     error::Error,
-    fs::File,
-    io::{self, BufRead, BufReader},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, IsTerminal},
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
-use clap::{Arg, Command};
+use chrono::NaiveDate;
+use clap::{parser::ValueSource, value_parser, Arg, ArgAction, Command};
+use log::{debug, error, info, warn, LevelFilter};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use statrs::statistics::Statistics;
 
-use crate::calculations::{calculate_price_paths, run_forest};
-use crate::stock::Stock;
+use crate::analysis::{
+    format_grouped, format_table, AnalysisResult, Direction, MoneyFormat, Outlook,
+};
+use crate::calculations::{
+    bucket_volumes, calculate_beta, calculate_drift, calculate_percentile, calculate_price_paths,
+    calculate_returns, calculate_rolling_beta, cross_validate, detect_splits,
+    discounted_expected_price, explain_prediction, find_stale_runs, first_passage_days,
+    fraction_above, fraction_below, implied_volatility, label_stocks, lag1_autocorrelation,
+    merge_series, transform_volumes, vote_forest, ForestOptions, ForestResult, ForestVote,
+    MergeMode, SimulationOptions, SplitCriterion, TrainedForest, VolumeTransform, CV_FOLDS,
+    FOREST_VOTES, RECENCY_COPIES, RSI_WINDOW, SMA_LONG_WINDOW, SMA_SHORT_WINDOW, TRIALS,
+    VOLUME_BUCKET_WINDOW, VOLUME_TRANSFORMS,
+};
+use crate::calendar::TradingCalendar;
+#[cfg(feature = "plot")]
+use crate::chart::plot_forecast;
+use crate::model::save_model;
+use crate::prediction::{
+    predict_with_details, FilePrediction, PredictionConfig, PredictionDetails, PredictionResult,
+};
+use crate::settings::{flag_value, load_repro_arguments, without_flag, Settings};
 use crate::stock::Tomorrow;
+use crate::stock::{feature_name, parse_date, ReturnType, ENGINEERED_FEATURE_NAMES, FEATURE_NAMES};
+use crate::stock::{Period, Stock};
 
+pub mod analysis;
 pub mod calculations;
+pub mod calendar;
+#[cfg(feature = "plot")]
+pub mod chart;
+pub mod model;
+pub mod prediction;
+pub mod settings;
 pub mod stock;
 
-type CustomResult<T> = Result<T, Box<dyn Error>>;
+pub(crate) type CustomResult<T> = Result<T, Box<dyn Error>>;
+
+// set by the Ctrl-C handler of main, long runs stop cleanly at the next check of interrupted
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// written as schema_version at the top of every JSON output, bumped whenever the structure of one changes
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+
+// number of days ahead the price is forecast without --days or --target-date
+const DEFAULT_DAYS: usize = 29;
+// fewest rows of a file for the drift, variance and labels to be defined and two earlier rows to train on
+pub const MIN_ROWS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    // one sentence per prediction as each file is analyzed
+    Text,
+    // one aligned row per file once every file is analyzed
+    Table,
+    // one JSON line per forest of the vote as each file is analyzed
+    NdjsonStats,
+    // one JSON object per file with its prediction as each file is analyzed
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DupPolicy {
+    // keep the first row of a date
+    First,
+    // keep the last row of a date, which is usually the correction
+    Last,
+    // average the prices and sum the volumes of the rows of a date
+    Average,
+    // reject a file with two rows of the same date
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseConfig {
+    // character separating the fields of a row
+    pub delimiter: char,
+    // numbers are written like 1.234,56 with a decimal comma
+    pub decimal_comma: bool,
+    // reject rows which break the OHLC invariants checked by Stock::validate
+    pub strict: bool,
+    // returns stored on each stock, see --return-type
+    pub return_type: ReturnType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub name: &'static str,
+    // number of rows where the field parsed
+    pub parsed: usize,
+    // number of rows where the field is missing or did not parse
+    pub failed: usize,
+    // line number and field of the first failure
+    pub first_failure: Option<(usize, String)>,
+}
+
+// the settings which change the prediction of a file, hashed into its provenance and written to the --repro-out
+// manifest, so output-only settings such as --report or --threads leave them alone
+#[derive(Debug, Serialize)]
+struct PredictionSettings<'a> {
+    target_date: Option<String>,
+    holidays: Vec<String>,
+    days: usize,
+    trials: usize,
+    seed: u64,
+    since: Option<String>,
+    until: Option<String>,
+    period: &'a str,
+    periods_per_year: f64,
+    features: &'a [usize],
+    invert: bool,
+    trees: usize,
+    max_features: Option<usize>,
+    criterion: &'a str,
+    train_frac: f32,
+    recency_weight: Option<f64>,
+    max_votes: usize,
+    target_stability: Option<f64>,
+    chunk_size: Option<usize>,
+    warmup: Option<usize>,
+    horizon_sweep: &'a [usize],
+    risk_free_rate: Option<f64>,
+    start_price: Option<f64>,
+    bootstrap_returns: bool,
+    dup_policy: DupPolicy,
+    merge_mode: Option<MergeMode>,
+    detect_splits: Option<f64>,
+    adjust_splits: bool,
+    volume_buckets: Option<usize>,
+    volume_transform: &'a str,
+    engineered_features: bool,
+    predict_index: Option<usize>,
+    load_model: Option<&'a str>,
+    prob_threshold: Option<f64>,
+    weighted_vote: bool,
+    delimiter: char,
+    decimal_comma: bool,
+    strict: bool,
+    return_type: &'a str,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Config {
     files: Vec<String>,
+    // every --glob pattern with the number of files it added to files
+    glob_matches: Vec<(String, usize)>,
+    target_date: Option<NaiveDate>,
+    // trading days of daily data, used to map between horizons and dates
+    calendar: TradingCalendar,
+    // number of days ahead the price is forecast when no target date is given
+    days: usize,
+    trials: usize,
+    // seed of the random number generator of every file, drawn at random without --seed
+    seed: u64,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    period: Period,
+    periods_per_year: f64,
+    forest: ForestOptions,
+    chunk_size: Option<usize>,
+    threads: Option<usize>,
+    debug_paths: usize,
+    fan_out: Option<String>,
+    returns_out: Option<String>,
+    dump_table: Option<String>,
+    #[cfg(feature = "plot")]
+    plot_out: Option<String>,
+    warmup: Option<usize>,
+    horizon_sweep: Vec<usize>,
+    vol_term_structure: bool,
+    discount_rate: Option<f64>,
+    report: Option<String>,
+    repro_out: Option<String>,
+    // command line the config was resolved from, --config expanded, for --repro-out
+    arguments: Vec<String>,
+    save_model: Option<String>,
+    load_model: Option<String>,
+    predict_index: Option<usize>,
+    params_only: bool,
+    validate: bool,
+    // print the features of the random forest and exit, see --list-features
+    list_features: bool,
+    print_config: bool,
+    // examples printed by --help-examples, built from the names of the arguments
+    help_examples: Option<String>,
+    dup_policy: DupPolicy,
+    merge_mode: Option<MergeMode>,
+    // round trip every result through JSON, see --self-test
+    self_test: bool,
+    risk_free_rate: Option<f64>,
+    start_price: Option<f64>,
+    prob_above: Option<f64>,
+    prob_below: Option<f64>,
+    first_passage: Option<f64>,
+    volume_buckets: Option<usize>,
+    volume_transform: VolumeTransform,
+    benchmark_file: Option<String>,
+    // window of returns and the CSV the rolling beta is written to, see --rolling-beta
+    rolling_beta: Option<(usize, String)>,
+    detect_splits: Option<f64>,
+    stale_threshold: usize,
+    adjust_splits: bool,
+    engineered_features: bool,
+    log_level: LevelFilter,
+    bootstrap_returns: bool,
+    grid: Vec<ForestOptions>,
+    parse: ParseConfig,
+    prob_threshold: Option<f64>,
+    format: OutputFormat,
+    group_by_direction: bool,
+    weighted_vote: bool,
+    explain_prediction: bool,
+    // print how many forests of the vote agree with its majority, see --meta-forests
+    meta_forests: bool,
+    // print the rows each tree is expected to leave out of its bootstrap sample, see --show-oob
+    show_oob: bool,
+    // fail a file whose raw test accuracy in percent is below this, see --assert-accuracy
+    assert_accuracy: Option<f32>,
+    show_provenance: bool,
+    metrics: bool,
+    money: MoneyFormat,
+}
+
+impl Config {
+    /*
+        Simple getter for the log level chosen with --verbose or --quiet
+
+        @param (&self) current config object
+
+        @return (LevelFilter) most verbose level of log messages the CLI should print
+    */
+    pub fn get_log_level(&self) -> LevelFilter {
+        self.log_level
+    }
+
+    /*
+        Returns the trading calendar when the data is daily, the other periods keep scaling calendar days by
+        --periods-per-year
+
+        @param (&self) current config object
+
+        @return (Option<&TradingCalendar>) the calendar for daily data, None otherwise
+    */
+    fn trading_calendar(&self) -> Option<&TradingCalendar> {
+        (self.period == Period::Day).then_some(&self.calendar)
+    }
+
+    /*
+        Collects the settings which change the prediction of a file
+
+        @param (&self) current config object
+
+        @return (PredictionSettings) the settings, without the input files and the output-only settings
+    */
+    fn prediction_settings(&self) -> PredictionSettings<'_> {
+        PredictionSettings {
+            target_date: self.target_date.map(|date| date.to_string()),
+            holidays: self
+                .calendar
+                .get_holidays()
+                .iter()
+                .map(|date| date.to_string())
+                .collect(),
+            days: self.days,
+            trials: self.trials,
+            seed: self.seed,
+            since: self.since.map(|date| date.to_string()),
+            until: self.until.map(|date| date.to_string()),
+            period: self.period.get_label(),
+            periods_per_year: self.periods_per_year,
+            features: &self.forest.features,
+            invert: self.forest.invert,
+            trees: self.forest.trees,
+            max_features: self.forest.max_features,
+            criterion: self.forest.criterion.get_name(),
+            train_frac: self.forest.train_frac,
+            recency_weight: self.forest.recency_weight,
+            max_votes: self.forest.max_votes,
+            target_stability: self.forest.target_stability,
+            chunk_size: self.chunk_size,
+            warmup: self.warmup,
+            horizon_sweep: &self.horizon_sweep,
+            risk_free_rate: self.risk_free_rate,
+            start_price: self.start_price,
+            bootstrap_returns: self.bootstrap_returns,
+            dup_policy: self.dup_policy,
+            merge_mode: self.merge_mode,
+            detect_splits: self.detect_splits,
+            adjust_splits: self.adjust_splits,
+            volume_buckets: self.volume_buckets,
+            volume_transform: self.volume_transform.get_name(),
+            engineered_features: self.engineered_features,
+            predict_index: self.predict_index,
+            load_model: self.load_model.as_deref(),
+            prob_threshold: self.prob_threshold,
+            weighted_vote: self.weighted_vote,
+            delimiter: self.parse.delimiter,
+            decimal_comma: self.parse.decimal_comma,
+            strict: self.parse.strict,
+            return_type: self.parse.return_type.get_name(),
+        }
+    }
+
+    /*
+        Checks if the results of each file are printed as soon as the file is analyzed
+
+        @param (&self) current config object
+
+        @return (bool) true for --format text without --group-by-direction
+    */
+    fn prints_as_it_goes(&self) -> bool {
+        self.format == OutputFormat::Text && !self.group_by_direction
+    }
 }
 
 /*
-    Attempt to open passed files and then parse them into stock objects, passing it to the desired method of prediction
+    Attempt to open passed files and then parse them into stock objects, passing it to the desired method of prediction.
+    A file which fails, even by panicking, is logged and skipped so the remaining files are still analyzed
 
     @param (config: Config) config object constructed by the get_args function
 
-    @return (CustomResult()) custom result object which indicates that the function has finished
+    @return (CustomResult()) custom result object which indicates that the function has finished, or an error
+    summarizing how many files failed
 */
 pub fn run(config: Config) -> CustomResult<()> {
-    for filename in config.files {
-        match open_file(&filename) {
-            Err(e) => eprintln!("{}: {}", filename, e),
-            Ok(file) => {
-                println!("{} Successfully Opened! Parsing Data...", filename);
-
-                let mut stock_vec: Vec<Stock> = Vec::new();
-
-                for (line_number, line) in file.lines().enumerate() {
-                    if line_number == 0 {
-                        continue;
-                    }
-                    let line: String = line.unwrap_or_else(|_| String::from(""));
-
-                    if line.is_empty() {
-                        continue;
-                    } else {
-                        let line_vec: Vec<&str> = line.split(',').collect();
-                        let stock: Stock = Stock::new(
-                            String::from(line_vec[0]),
-                            line_vec[1].parse().unwrap(),
-                            line_vec[2].parse().unwrap(),
-                            line_vec[3].parse().unwrap(),
-                            line_vec[4].parse().unwrap(),
-                            line_vec[5].parse().unwrap(),
-                            line_vec[6].parse().unwrap(),
-                            Tomorrow::Predict,
-                        );
-                        stock_vec.push(stock);
-                    }
-                }
+    if config.print_config {
+        eprintln!("{:#?}", config);
+    }
 
-                let length = stock_vec.len();
-                for i in 0..(length - 1) {
-                    if stock_vec[i].get_price() <= stock_vec[i + 1].get_price() {
-                        stock_vec[i].set_tomorrow(Tomorrow::Increase);
-                    } else {
-                        stock_vec[i].set_tomorrow(Tomorrow::Decrease);
-                    }
-                }
+    if let Some(examples) = &config.help_examples {
+        print!("{}", examples);
+        return Ok(());
+    }
 
-                for i in 0..(length - 1) {
-                    let curr_price = stock_vec[i].get_price();
-                    stock_vec[i + 1].set_return(curr_price);
-                }
+    if config.parse.return_type == ReturnType::Simple && !config.list_features {
+        warn!("The price paths compound exp(return) as log returns do, so with --return-type simple the forecast is only approximate");
+    }
 
-                let price_paths = calculate_price_paths(&stock_vec);
+    if config.list_features {
+        print!("{}", format_feature_list(&config));
+        return Ok(());
+    }
+
+    let mut results: Vec<AnalysisResult> = Vec::new();
+    let mut failed: usize = 0;
 
-                let predicted: f64 = price_paths[price_paths.len() - 1].clone().iter().mean();
+    // 0 threads is the rayon default of one per core
+    let pool: ThreadPool = ThreadPoolBuilder::new()
+        .num_threads(config.threads.unwrap_or(0))
+        .build()?;
 
-                println!("Monte Carlo methods predict a price of {}!", predicted);
+    for (pattern, matched) in &config.glob_matches {
+        if *matched == 0 {
+            warn!("--glob {} matched no files", pattern);
+        } else {
+            info!("--glob {} matched {} files", pattern, matched);
+        }
+    }
 
-                let mut num_inc: i32 = 0;
-                let mut num_dec: i32 = 0;
-                let mut avg_acc = 0.0;
+    // the merged files are analyzed as one series
+    let inputs: Vec<String> = match config.merge_mode {
+        Some(_) => vec![config.files.join("+")],
+        None => config.files.clone(),
+    };
 
-                for _ in 0..10 {
-                    let (res, accuracy) = run_forest(&stock_vec);
+    let mut attempted: usize = 0;
+    for filename in &inputs {
+        if interrupted() {
+            warn!("Interrupted, the remaining files are skipped");
+            break;
+        }
+        attempted += 1;
 
-                    if res == 1.0 {
-                        num_inc += 1;
-                    } else {
-                        num_dec += 1;
-                    }
+        let analysis = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.install(|| analyze_file(filename, &config).map_err(|e| e.to_string()))
+        }));
 
-                    avg_acc += accuracy;
-                }
+        match analysis {
+            Ok(Ok(Some(result))) => results.push(result),
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => {
+                error!("{}: {}", filename, e);
+                failed += 1;
+            }
+            Err(payload) => {
+                let message: &str = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                error!("{}: the analysis panicked: {}", filename, message);
+                failed += 1;
+            }
+        }
+    }
+
+    if config.group_by_direction {
+        print!("{}", format_grouped(&results, &config.money));
+    } else if config.format == OutputFormat::Table {
+        print!("{}", format_table(&results, &config.money));
+    }
+
+    if let Some(path) = &config.report {
+        write_report(path, &results, &config.money, interrupted())?;
+        info!("Report written to {}", path);
+    }
+
+    if let Some(path) = &config.repro_out {
+        write_manifest(path, &config, &results)?;
+        info!("Reproduction manifest written to {}", path);
+    }
+
+    let summary: String = format!(
+        "{} of {} files analyzed, {} failed",
+        attempted - failed,
+        inputs.len(),
+        failed
+    );
+    if interrupted() {
+        return Err(format!("interrupted: {}", summary).into());
+    }
+    if failed > 0 {
+        return Err(summary.into());
+    }
+    info!("{}", summary);
+
+    Ok(())
+}
+
+/*
+    Analyzes one file: the Monte Carlo forecast of its price and the random forest vote on its direction,
+    printing them as it goes in text mode
 
-                if num_inc >= num_dec {
-                    println!(
-                        "The Random Forest predicts an increase with a test accuracy of {}%!",
-                        avg_acc * 10.0
-                    );
-                } else {
-                    println!(
-                        "The Random Forest predicts a decrease with a test accuracy of {}!",
-                        avg_acc * 10.0
-                    );
+    @param (filename: &str) path of the file, "-" for stdin, or the joined paths of the files of --merge-mode
+    @param (config: &Config) config object constructed by the get_args function
+
+    @return (CustomResult<Option<AnalysisResult>>) CustomResult containing the analysis, None when only the
+    parameters were printed with --params-only
+*/
+fn analyze_file(filename: &str, config: &Config) -> CustomResult<Option<AnalysisResult>> {
+    let mut stock_vec: Vec<Stock> = match config.merge_mode {
+        Some(mode) => read_merged(&config.files, mode, &config.parse)?,
+        None => {
+            let file: Box<dyn BufRead> = open_file(filename)?;
+            info!("{} Successfully Opened! Parsing Data...", filename);
+
+            if config.validate {
+                let stats: Vec<ColumnStats> = column_stats(file, &config.parse)?;
+                print!("{}", format_column_stats(filename, &stats));
+
+                let failed: usize = stats.iter().map(|column| column.failed).sum();
+                if failed > 0 {
+                    return Err(format!("{} fields could not be parsed", failed).into());
                 }
+                return Ok(None);
+            }
+
+            parse_stocks(file, &config.parse)?
+        }
+    };
+    let rows_loaded: usize = stock_vec.len();
+    debug!("{}: parsed {} rows", filename, rows_loaded);
+    if stock_vec.is_empty() {
+        return Err("no data rows found after header".into());
+    }
+
+    stock_vec = reconcile_duplicates(
+        stock_vec,
+        config.dup_policy,
+        config.parse.return_type,
+        filename,
+    )?;
+    if stock_vec.len() < MIN_ROWS {
+        return Err(format!(
+            "has {} rows, too few to predict (need at least {})",
+            stock_vec.len(),
+            MIN_ROWS
+        )
+        .into());
+    }
+
+    if config.since.is_some() || config.until.is_some() {
+        stock_vec = filter_dates(
+            stock_vec,
+            config.since,
+            config.until,
+            config.parse.return_type,
+        )?;
+        debug!("{}: {} rows in the date range", filename, stock_vec.len());
+    }
+    let length: usize = stock_vec.len();
+
+    let provenance: String = calculate_provenance(&stock_vec, config)?;
+    if config.show_provenance && config.prints_as_it_goes() {
+        println!("{}: provenance {}", filename, provenance);
+    }
+
+    for (first, last) in find_stale_runs(&stock_vec, config.stale_threshold) {
+        let ending: &str = if last == length - 1 {
+            ", the feed may be stale and the forecast flat"
+        } else {
+            ""
+        };
+        warn!(
+            "{}: {} identical rows from {} to {}{}",
+            filename,
+            last - first + 1,
+            stock_vec[first].get_date_string(),
+            stock_vec[last].get_date_string(),
+            ending
+        );
+    }
+
+    let mut split_dates: Vec<String> = Vec::new();
+    if let Some(threshold) = config.detect_splits {
+        for idx in detect_splits(&stock_vec, threshold) {
+            warn!(
+                "{}: possible split or dividend on {}, the close changed {:.4}x but adj_close {:.4}x",
+                filename,
+                stock_vec[idx].get_date_string(),
+                stock_vec[idx].get_close() / stock_vec[idx - 1].get_close(),
+                stock_vec[idx].get_adj_close() / stock_vec[idx - 1].get_adj_close()
+            );
+            split_dates.push(stock_vec[idx].get_date_string());
+        }
+
+        if !split_dates.is_empty() && config.adjust_splits {
+            for stock in stock_vec.iter_mut() {
+                stock.adjust_prices();
+            }
+            label_stocks(&mut stock_vec, config.parse.return_type);
+            info!("{}: prices are adjusted by adj_close", filename);
+        }
+    }
+
+    // the annualized rate is converted to a continuously compounded rate per period
+    let risk_free_rate: Option<f64> = config
+        .risk_free_rate
+        .map(|rate| rate / config.periods_per_year);
+
+    let returns: Vec<f64> = calculate_returns(&stock_vec, config.parse.return_type);
+    if let Some(path) = &config.returns_out {
+        write_returns(path, &stock_vec, &returns, config.parse.return_type)?;
+        info!("Returns written to {}", path);
+    }
+    let autocorrelation: f64 = lag1_autocorrelation(&returns);
+    // beyond about two standard errors of an independent series, 1 / sqrt(n) each
+    let dependent_returns: bool = autocorrelation.abs() > 2.0 / (returns.len() as f64).sqrt();
+
+    let benchmark: Option<Vec<Stock>> = match &config.benchmark_file {
+        Some(path) => Some(
+            parse_stocks(open_file(path)?, &config.parse)
+                .map_err(|e| format!("benchmark {}: {}", path, e))?,
+        ),
+        None => None,
+    };
+    let beta: Option<(f64, f64)> = match &benchmark {
+        Some(benchmark) => Some(calculate_beta(
+            &stock_vec,
+            benchmark,
+            config.parse.return_type,
+        )?),
+        None => None,
+    };
+    if let (Some(benchmark), Some((window, path))) = (&benchmark, &config.rolling_beta) {
+        let series: Vec<(String, f64)> =
+            calculate_rolling_beta(&stock_vec, benchmark, *window, config.parse.return_type)?;
+        write_rolling_beta(path, &series)?;
+        info!(
+            "Beta over rolling windows of {} returns written to {}",
+            window, path
+        );
+    }
+
+    if config.params_only {
+        let (drift, variance) = calculate_drift(&stock_vec, risk_free_rate);
+        let daily_vol: f64 = variance.sqrt();
+
+        let mut params = json!({
+            "schema_version": JSON_SCHEMA_VERSION,
+            "file": filename,
+            "period": config.period.get_label(),
+            "provenance": provenance,
+            "drift": drift,
+            "variance": variance,
+            "daily_vol": daily_vol,
+            "annual_vol": daily_vol * config.periods_per_year.sqrt(),
+        });
+        if config.metrics {
+            params["lag1_autocorrelation"] = json!(autocorrelation);
+            params["rows_loaded"] = json!(rows_loaded);
+            params["rows_used"] = json!(length);
+            params["drift_returns"] = json!(length);
+            params["effective_sample_size"] = json!(length);
+        }
+        if let Some((beta, alpha)) = beta {
+            params["beta"] = json!(beta);
+            params["alpha"] = json!(alpha);
+            params["annual_alpha"] = json!(alpha * config.periods_per_year);
+        }
+        println!("{}", params);
+        return Ok(None);
+    }
+
+    if let Some((beta, alpha)) = beta.filter(|_| config.prints_as_it_goes()) {
+        println!(
+            "Against the benchmark the beta is {:.4} and the alpha is {:.4}% per year",
+            beta,
+            alpha * config.periods_per_year * 100.0
+        );
+    }
+
+    if config.metrics && config.prints_as_it_goes() {
+        println!(
+            "{} rows were loaded and {} are left after reconciling duplicate dates and the date range",
+            rows_loaded, length
+        );
+        // calculate_drift averages the returns of every row, the first one has none and counts as 0
+        println!(
+            "The drift and volatility are estimated from {} returns, the first a zero return. Every return is weighted alike, so the effective sample size is also {}",
+            length, length
+        );
+        println!(
+            "The lag-1 autocorrelation of the returns is {:.4}",
+            autocorrelation
+        );
+        if dependent_returns {
+            println!("The returns do not look independent, so the Monte Carlo forecast should be discounted");
+        }
+    }
+
+    let predict_index: usize = config.predict_index.unwrap_or(length - 1);
+    if predict_index >= length {
+        return Err(format!(
+            "--predict-index {} is out of range, the file has {} rows",
+            predict_index, length
+        )
+        .into());
+    } else if predict_index < 2 {
+        return Err(format!(
+            "--predict-index {} leaves too few earlier rows to train on",
+            predict_index
+        )
+        .into());
+    }
+
+    let days: usize = match config.target_date {
+        Some(target) => {
+            calculate_horizon(
+                &stock_vec[length - 1],
+                target,
+                config.periods_per_year,
+                config.trading_calendar(),
+            )? + 1
+        }
+        None => config.days + 1,
+    };
+
+    debug!(
+        "{}: simulating {} days with {} trials",
+        filename, days, config.trials
+    );
+
+    let simulation: SimulationOptions = SimulationOptions {
+        days,
+        trials: config.trials,
+        risk_free_rate,
+        bootstrap: config.bootstrap_returns,
+        start_price: config.start_price,
+    };
+
+    let mut forest: ForestOptions = config.forest.clone();
+    if config.engineered_features {
+        forest
+            .features
+            .extend(FEATURE_NAMES.len()..FEATURE_NAMES.len() + ENGINEERED_FEATURE_NAMES.len());
+    }
+
+    // every file starts from the seed, so its results do not depend on the files before it. The comparisons,
+    // the horizon sweep and the grid draw from a generator of their own, seeded like the one of the prediction
+    let mut rng: StdRng = StdRng::seed_from_u64(config.seed);
+
+    if let Some(lambda) = config.forest.recency_weight {
+        if config.prints_as_it_goes() {
+            let unweighted: ForestOptions = ForestOptions {
+                recency_weight: None,
+                ..config.forest.clone()
+            };
+            let unweighted_vote: ForestVote =
+                vote_forest(&stock_vec, &unweighted, predict_index, &mut rng)?;
+            println!(
+                "Without recency weighting the Random Forest has a test accuracy of {}%",
+                config.money.accuracy(unweighted_vote.accuracy)
+            );
+        }
+        info!(
+            "Training rows are weighted by exp(-{} * age), the newest up to {} times",
+            lambda, RECENCY_COPIES
+        );
+    }
+
+    if let Some(buckets) = config.volume_buckets {
+        if config.prints_as_it_goes() {
+            let raw_vote: ForestVote =
+                vote_forest(&stock_vec, &config.forest, predict_index, &mut rng)?;
+            println!(
+                "Without volume buckets the Random Forest has a test accuracy of {}%",
+                config.money.accuracy(raw_vote.accuracy)
+            );
+        }
+
+        bucket_volumes(&mut stock_vec, buckets);
+        info!(
+            "Volume is replaced by its bucket out of {} over the trailing {} days",
+            buckets, VOLUME_BUCKET_WINDOW
+        );
+    }
+
+    if config.volume_transform != VolumeTransform::None {
+        if config.prints_as_it_goes() {
+            for transform in VOLUME_TRANSFORMS {
+                let mut transformed_vec: Vec<Stock> = stock_vec.clone();
+                transform_volumes(&mut transformed_vec, transform, predict_index);
+                let transform_vote: ForestVote =
+                    vote_forest(&transformed_vec, &config.forest, predict_index, &mut rng)?;
+                println!(
+                    "With the {} volume transform the Random Forest has a test accuracy of {}%",
+                    transform.get_name(),
+                    config.money.accuracy(transform_vote.accuracy)
+                );
             }
         }
+
+        transform_volumes(&mut stock_vec, config.volume_transform, predict_index);
+        info!(
+            "Volume is replaced by its {} transform",
+            config.volume_transform.get_name()
+        );
+    }
+
+    // the comparison is only printed in the text output
+    if config.engineered_features && config.prints_as_it_goes() {
+        let raw_vote: ForestVote =
+            vote_forest(&stock_vec, &config.forest, predict_index, &mut rng)?;
+        println!(
+            "Without engineered features the Random Forest has a test accuracy of {}%",
+            config.money.accuracy(raw_vote.accuracy)
+        );
+    }
+
+    let prediction_config: PredictionConfig = PredictionConfig {
+        simulation,
+        forest: forest.clone(),
+        prob_threshold: config.prob_threshold,
+        weighted_vote: config.weighted_vote,
+        seed: Some(config.seed),
+        chunk_size: config.chunk_size,
+        warmup: config.warmup,
+        predict_index: Some(predict_index),
+        engineered_features: config.engineered_features,
+        load_model: config.load_model.clone(),
+    };
+    let (prediction, details): (PredictionResult, PredictionDetails) =
+        predict_with_details(&stock_vec, &prediction_config)?;
+    let simulated_prices: &[f64] = &details.simulated_prices;
+
+    if let Some(price_paths) = &details.price_paths {
+        if config.debug_paths > 0 {
+            print_debug_paths(price_paths, config.debug_paths);
+        }
+        if let Some(target) = config.first_passage.filter(|_| config.prints_as_it_goes()) {
+            print_first_passage(price_paths, target, &config.money);
+        }
+        if let Some(path) = &config.fan_out {
+            write_fan_chart(path, price_paths)?;
+            info!("Fan chart percentiles written to {}", path);
+        }
+        #[cfg(feature = "plot")]
+        if let Some(path) = &config.plot_out {
+            plot_forecast(
+                path,
+                &stock_vec,
+                price_paths,
+                config.periods_per_year,
+                config.trading_calendar(),
+            )?;
+            info!("Forecast chart written to {}", path);
+        }
+    }
+    if let Some(warmup) = config.warmup {
+        info!(
+            "Reporting the simulated prices of days {} to {} of every path",
+            warmup,
+            days - 1
+        );
+    }
+
+    let current_price: f64 = prediction_config.simulation.start_price(&stock_vec);
+    if config.start_price.is_some() && config.prints_as_it_goes() {
+        println!(
+            "Scenario: the simulation starts from {} instead of the last price {}",
+            config.money.format(current_price),
+            config.money.format(stock_vec[length - 1].get_price())
+        );
+    }
+    if config.prints_as_it_goes() {
+        let horizon: String = match (config.warmup, config.target_date) {
+            (Some(warmup), _) => format!("from day {} on", warmup),
+            (None, Some(target)) => format!("on {}", target),
+            (None, None) => format!("after {} days", days - 1),
+        };
+        if let Some(threshold) = config.prob_above {
+            println!(
+                "The simulated price {} is above {} with a probability of {:.*}%",
+                horizon,
+                config.money.format(threshold),
+                config.money.decimals(),
+                fraction_above(simulated_prices, threshold) * 100.0
+            );
+        }
+        if let Some(threshold) = config.prob_below {
+            println!(
+                "The simulated price {} is below {} with a probability of {:.*}%",
+                horizon,
+                config.money.format(threshold),
+                config.money.decimals(),
+                fraction_below(simulated_prices, threshold) * 100.0
+            );
+        }
+    }
+
+    let outlook_band: (f64, f64) = (
+        calculate_percentile(simulated_prices, 25.0),
+        calculate_percentile(simulated_prices, 75.0),
+    );
+    let outlook: Outlook = Outlook::classify(current_price, outlook_band);
+
+    // the sweep is only printed in the text output
+    if let Some(longest) = config
+        .horizon_sweep
+        .last()
+        .filter(|_| config.prints_as_it_goes())
+    {
+        // one set of longer paths is read at every horizon instead of simulating each one
+        let sweep: SimulationOptions = SimulationOptions {
+            days: longest + 1,
+            ..prediction_config.simulation
+        };
+        let sweep_paths: Vec<Vec<f64>> = calculate_price_paths(&stock_vec, &sweep, &mut rng)?;
+        print_horizon_sweep(&sweep_paths, &config.horizon_sweep, &config.money);
+        if config.vol_term_structure {
+            print_vol_term_structure(
+                &sweep_paths,
+                &config.horizon_sweep,
+                config.periods_per_year,
+                &config.money,
+            );
+        }
+
+        if let Some(rate) = config.discount_rate {
+            let value: f64 = discounted_expected_price(
+                &sweep_paths,
+                &config.horizon_sweep,
+                rate / config.periods_per_year,
+            );
+            println!(
+                "Discounted at {}% a year, the expected price over the horizons is worth {} today",
+                rate * 100.0,
+                config.money.format(value)
+            );
+        }
+    }
+
+    if config.predict_index.is_some() {
+        info!(
+            "The Random Forest is predicting the day after {} (row {})",
+            stock_vec[predict_index].get_date_string(),
+            predict_index
+        );
+    }
+
+    let feature_names: Vec<&str> = forest
+        .features
+        .iter()
+        .map(|idx| feature_name(*idx))
+        .collect();
+    info!(
+        "The Random Forest is using the features: {}",
+        feature_names.join(", ")
+    );
+
+    if let Some(path) = &config.dump_table {
+        write_training_table(
+            path,
+            &details.forest_stocks[0..details.forest_index],
+            &forest.features,
+        )?;
+        info!(
+            "Wrote the {} training rows to {}",
+            details.forest_index, path
+        );
+    }
+
+    match &config.load_model {
+        Some(path) if details.stale_model => warn!(
+            "{}: the model in {} was trained on different data and may be stale",
+            filename, path
+        ),
+        Some(_) => {}
+        None if forest.target_stability.is_some() => info!(
+            "{}: the vote stopped after {} of at most {} forests",
+            filename,
+            details.forests.len(),
+            forest.max_votes
+        ),
+        None => {}
+    }
+
+    if let Some(path) = &config.save_model {
+        match save_model(
+            path,
+            details.fingerprint,
+            &forest.features,
+            &details.forests,
+        ) {
+            Ok(()) => info!("Saved the trained forests to {}", path),
+            Err(e) => error!("could not save the model to {}: {}", path, e),
+        }
+    }
+
+    if config.show_oob && config.prints_as_it_goes() {
+        print_out_of_bag(&details.forests);
+    }
+
+    if config.format == OutputFormat::NdjsonStats {
+        print_forest_stats(
+            filename,
+            &details.forests,
+            &details.forest_stocks[details.forest_index],
+            &forest.features,
+        );
+    }
+
+    let vote: &ForestVote = &details.vote;
+
+    if let Some(threshold) = config.assert_accuracy {
+        if details.vote.raw_accuracy * 100.0 < threshold {
+            return Err(format!(
+                "the test accuracy of {:.2}%, before inverting inaccurate forests, is below --assert-accuracy {}%",
+                details.vote.raw_accuracy * 100.0,
+                threshold
+            )
+            .into());
+        }
+    }
+
+    let result: AnalysisResult = AnalysisResult {
+        filename: filename.to_string(),
+        rows: length,
+        first_date: stock_vec[0].get_date_string(),
+        last_date: stock_vec[length - 1].get_date_string(),
+        current_price,
+        days,
+        target_date: config.target_date,
+        trials: config.trials,
+        predicted_price: prediction.mean_price,
+        risk_neutral: risk_free_rate.is_some(),
+        confidence_interval: (
+            calculate_percentile(simulated_prices, 5.0),
+            calculate_percentile(simulated_prices, 95.0),
+        ),
+        outlook_band,
+        outlook,
+        features: forest.features,
+        split_dates,
+        provenance,
+        period: config.period,
+        direction: prediction.direction,
+        prob_threshold: config.prob_threshold,
+        weighted_increase: config.weighted_vote.then_some(vote.weighted_increase),
+        probability: vote.probability,
+        accuracy: prediction.accuracy,
+        confusion: vote.confusion,
+    };
+
+    if config.prints_as_it_goes() {
+        print!("{}", result.to_text(&config.money));
+    } else if config.format == OutputFormat::Json {
+        let line: FilePrediction = FilePrediction {
+            schema_version: JSON_SCHEMA_VERSION,
+            file: filename,
+            provenance: &result.provenance,
+            prediction: &prediction,
+        };
+        println!("{}", serde_json::to_string(&line)?);
+    }
+
+    if config.meta_forests && config.prints_as_it_goes() {
+        println!(
+            "{} of {} independent forests agree on the direction, a meta-confidence of {}%",
+            vote.num_inc.max(vote.num_dec),
+            vote.num_inc + vote.num_dec,
+            config.money.percent(vote.agreement())
+        );
+    }
+
+    if config.explain_prediction && config.prints_as_it_goes() {
+        let contributions: Vec<(usize, f64)> = explain_prediction(
+            &details.forests,
+            &details.forest_stocks,
+            &result.features,
+            details.forest_index,
+        );
+        for (feature, contribution) in &contributions {
+            debug!(
+                "{}: {} changes the increase probability by {:+.3}",
+                filename,
+                feature_name(*feature),
+                contribution
+            );
+        }
+        print_explanation(&contributions, vote.probability >= 0.5);
+    }
+
+    if !config.grid.is_empty() {
+        print_grid(&stock_vec, &config.grid, predict_index, &mut rng)?;
+    }
+
+    if config.self_test {
+        check_round_trip(&result)?;
+        println!("{}: the result survives a JSON round trip", filename);
+    }
+
+    Ok(Some(result))
+}
+
+/*
+    Prints the features which pushed the forests toward the predicted direction the most
+
+    @param (contributions: &[(usize, f64)]) contributions calculated by explain_prediction, largest first
+    @param (increase: bool) whether the forests lean toward an increase
+*/
+fn print_explanation(contributions: &[(usize, f64)], increase: bool) {
+    let drivers: Vec<String> = contributions
+        .iter()
+        .filter(|(_, contribution)| (*contribution > 0.0) == increase && *contribution != 0.0)
+        .take(3)
+        .map(|(feature, contribution)| format!("{} ({:+.2})", feature_name(*feature), contribution))
+        .collect();
+    let direction: &str = if increase { "increase" } else { "decrease" };
+
+    if drivers.is_empty() {
+        println!(
+            "No single feature moves the forests toward a {} on its own",
+            direction
+        );
+    } else {
+        println!(
+            "The {} is driven mainly by: {}",
+            direction,
+            drivers.join(", ")
+        );
+    }
+}
+
+/*
+    Writes a Markdown report with one section for every analyzed file
+
+    @param (path: &str) path of the Markdown file to create
+    @param (results: &[AnalysisResult]) analysis results of the files that were processed
+    @param (money: &MoneyFormat) format of the prices
+    @param (interrupted: bool) whether the run was interrupted, which is marked at the top of the report
+
+    @return (CustomResult()) custom result object which indicates that the report was written
+*/
+fn write_report(
+    path: &str,
+    results: &[AnalysisResult],
+    money: &MoneyFormat,
+    interrupted: bool,
+) -> CustomResult<()> {
+    let mut markdown: String = String::from("# rusty_stocks Analysis Report\n\n");
+    if interrupted {
+        markdown
+            .push_str("**Interrupted**: only the files analyzed before Ctrl-C are included\n\n");
     }
 
+    for result in results {
+        markdown.push_str(&result.to_markdown(money));
+        markdown.push('\n');
+    }
+
+    fs::write(path, markdown)?;
+
     Ok(())
 }
 
 /*
-    Opens a passed file which is in respect to the current working directory
+    Checks whether the run was interrupted with Ctrl-C
 
-    @param (filename: &str) relative file path which is used to open the stock data file
+    @return (bool) true once INTERRUPTED is set
+*/
+pub(crate) fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
 
-    @return (CustomResult<Box<dyn BufRead>>) CustomResult containing BufRead object used to read the passed file
+/*
+    Reconciles consecutive rows of the same date, which some feeds emit for a correction, and labels the
+    stocks again if any were merged
+
+    @param (stocks: Vec<Stock>) vector of stock objects in date order
+    @param (policy: DupPolicy) how the rows of one date are reconciled, see --dup-policy
+    @param (return_type: ReturnType) whether the returns set when labelling again are logarithmic or simple
+    @param (filename: &str) path of the file, to name it in the warning
+
+    @return (CustomResult<Vec<Stock>>) CustomResult containing one stock per date, or an error naming the
+    first repeated date under DupPolicy::Error
 */
-fn open_file(filename: &str) -> CustomResult<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+fn reconcile_duplicates(
+    stocks: Vec<Stock>,
+    policy: DupPolicy,
+    return_type: ReturnType,
+    filename: &str,
+) -> CustomResult<Vec<Stock>> {
+    let mut reconciled: Vec<Stock> = Vec::new();
+    let mut merged: usize = 0;
+
+    for group in stocks.chunk_by(|a, b| a.get_date_string() == b.get_date_string()) {
+        if group.len() > 1 {
+            if policy == DupPolicy::Error {
+                return Err(format!(
+                    "{} rows share the date {}, choose how to reconcile them with --dup-policy",
+                    group.len(),
+                    group[0].get_date_string()
+                )
+                .into());
+            }
+            merged += group.len() - 1;
+        }
+
+        reconciled.push(match policy {
+            DupPolicy::First => group[0].clone(),
+            DupPolicy::Average => Stock::average(group),
+            DupPolicy::Last | DupPolicy::Error => group[group.len() - 1].clone(),
+        });
+    }
+
+    if merged > 0 {
+        warn!(
+            "{}: {} rows repeat the date of the row before them and were reconciled by keeping the {}",
+            filename,
+            merged,
+            match policy {
+                DupPolicy::First => "first row",
+                DupPolicy::Average => "average",
+                DupPolicy::Last | DupPolicy::Error => "last row",
+            }
+        );
+
+        label_stocks(&mut reconciled, return_type);
+        let last: usize = reconciled.len() - 1;
+        reconciled[last].set_tomorrow(Tomorrow::Predict);
     }
+
+    Ok(reconciled)
 }
 
 /*
-    Parses the command line argument including the filepaths and the number of prediction days
+    Parses every file of --merge-mode and joins them into one series with merge_series
 
-    @return (CustomResult<Config>) CustomResult containing Config object holding passed arguments
+    @param (files: &[String]) paths of the files in the order they are joined, "-" for stdin
+    @param (mode: MergeMode) whether the files are scaled to join without a jump
+    @param (parse: &ParseConfig) how the files are parsed
+
+    @return (CustomResult<Vec<Stock>>) CustomResult containing the joined stocks, or the error of a file
 */
-pub fn get_args() -> CustomResult<Config> {
-    let mut matches = Command::new("rusty_stocks")
-        .version("0.1.0")
-        .author("Derek Warner <derekw3@illinois.edu>, Chengxun Ren <cren8@illinois.edu>, Haozhe Chen <haozhe6@illinois.edu>, Aaryan Singh Gusain <agusain2@illinois.edu>")
-        .about("A CLI stock prediction application")
-        .arg(
-            Arg::new("files")
-                .help("Input File(s)")
-                .default_value("-")
-                .num_args(1..),
+fn read_merged(files: &[String], mode: MergeMode, parse: &ParseConfig) -> CustomResult<Vec<Stock>> {
+    let mut series: Vec<(String, Vec<Stock>)> = Vec::new();
+    for filename in files {
+        let file: Box<dyn BufRead> = open_file(filename)?;
+        info!("{} Successfully Opened! Parsing Data...", filename);
+
+        let stocks: Vec<Stock> =
+            parse_stocks(file, parse).map_err(|e| format!("{}: {}", filename, e))?;
+        if stocks.is_empty() {
+            return Err(format!("{}: no data rows found after header", filename).into());
+        }
+        series.push((filename.clone(), stocks));
+    }
+
+    let (merged, ratios) = merge_series(&series, mode, parse.return_type)?;
+    if mode == MergeMode::Chain {
+        for ((filename, _), ratio) in series[1..].iter().zip(ratios) {
+            info!(
+                "{}: prices are scaled by {:.6} to chain it",
+                filename, ratio
+            );
+        }
+    }
+
+    Ok(merged)
+}
+
+/*
+    Keeps the stocks dated within a range and labels them again, so the last one kept is the one to predict
+
+    @param (stocks: Vec<Stock>) vector of stock objects in date order
+    @param (since: Option<NaiveDate>) first date kept, if any
+    @param (until: Option<NaiveDate>) last date kept, if any
+    @param (return_type: ReturnType) whether the returns set when labelling again are logarithmic or simple
+
+    @return (CustomResult<Vec<Stock>>) CustomResult containing the stocks in the range, or an error if a date
+    cannot be read or fewer than MIN_ROWS stocks are left
+*/
+fn filter_dates(
+    stocks: Vec<Stock>,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    return_type: ReturnType,
+) -> CustomResult<Vec<Stock>> {
+    let mut filtered: Vec<Stock> = Vec::new();
+    for stock in stocks {
+        let date: NaiveDate = stock
+            .get_date()
+            .map_err(|e| format!("could not read the date {}: {}", stock.get_date_string(), e))?;
+        if since.is_none_or(|since| date >= since) && until.is_none_or(|until| date <= until) {
+            filtered.push(stock);
+        }
+    }
+
+    if filtered.len() < MIN_ROWS {
+        return Err(format!(
+            "--since and --until leave {} rows, at least {} are needed",
+            filtered.len(),
+            MIN_ROWS
         )
-        .get_matches();
+        .into());
+    }
+
+    label_stocks(&mut filtered, return_type);
+    let last: usize = filtered.len() - 1;
+    filtered[last].set_tomorrow(Tomorrow::Predict);
+
+    Ok(filtered)
+}
+
+/*
+    Parses a YYYY-MM-DD date given on the command line
+
+    @param (flag: &str) name of the flag, for the error message
+    @param (date: Option<String>) value of the flag, if it was given
+
+    @return (CustomResult<Option<NaiveDate>>) CustomResult containing the parsed date, if one was given
+*/
+fn parse_date_flag(flag: &str, date: Option<String>) -> CustomResult<Option<NaiveDate>> {
+    match date {
+        Some(date) => match NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(e) => Err(format!("invalid {} \"{}\": {}", flag, date, e).into()),
+        },
+        None => Ok(None),
+    }
+}
+
+/*
+    Converts the gap between the last data date and a target date into a number of simulation periods. With a
+    trading calendar the periods are the trading days in between, otherwise calendar days are scaled by the
+    number of periods in a year
+
+    @param (last: &Stock) most recent stock object whose date anchors the simulation
+    @param (target: NaiveDate) date the user wants a prediction for
+    @param (periods_per_year: f64) number of data periods in one calendar year
+    @param (calendar: Option<&TradingCalendar>) trading days of daily data, None for other periods
+
+    @return (CustomResult<usize>) CustomResult containing the number of periods between the two dates
+*/
+fn calculate_horizon(
+    last: &Stock,
+    target: NaiveDate,
+    periods_per_year: f64,
+    calendar: Option<&TradingCalendar>,
+) -> CustomResult<usize> {
+    let last_date: NaiveDate = last
+        .get_date()
+        .map_err(|e| format!("could not read last data date: {}", e))?;
+
+    if target <= last_date {
+        return Err(format!(
+            "target date {} is not after the last data date {}",
+            target, last_date
+        )
+        .into());
+    }
+
+    let periods: usize = match calendar {
+        Some(calendar) => calendar.trading_days_between(last_date, target),
+        None => {
+            let calendar_days = (target - last_date).num_days() as f64;
+            (calendar_days * periods_per_year / 365.0).round() as usize
+        }
+    };
+
+    Ok(periods.max(1))
+}
+
+/*
+    Decides whether the text and table output is colored. With auto, stdout is colored when it is a terminal
+    and the NO_COLOR environment variable is unset or empty, so logs and pipes get plain text
+
+    @param (choice: Option<&str>) value of --color, "auto", "always" or "never", None for auto
+    @param (no_color: bool) whether --no-color is given
+
+    @return (bool) whether increases and decreases are colored
+*/
+fn use_color(choice: Option<&str>, no_color: bool) -> bool {
+    match choice {
+        _ if no_color => false,
+        Some("always") => true,
+        Some("never") => false,
+        _ => {
+            io::stdout().is_terminal()
+                && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        }
+    }
+}
+
+/*
+    Parses a comma separated list of feature names into indices of FEATURE_NAMES
+
+    @param (list: &str) comma separated feature names such as "open,high,low,close"
+
+    @return (CustomResult<Vec<usize>>) CustomResult containing the selected feature indices in the given order
+*/
+fn parse_features(list: &str) -> CustomResult<Vec<usize>> {
+    let mut features: Vec<usize> = Vec::new();
+
+    for name in list.split(',') {
+        let name: &str = name.trim();
+        match FEATURE_NAMES.iter().position(|feature| *feature == name) {
+            Some(idx) if !features.contains(&idx) => features.push(idx),
+            Some(_) => return Err(format!("feature \"{}\" was selected twice", name).into()),
+            None => {
+                return Err(format!(
+                    "unknown feature \"{}\", expected one of: {}",
+                    name,
+                    FEATURE_NAMES.join(", ")
+                )
+                .into())
+            }
+        }
+    }
+
+    Ok(features)
+}
+
+/*
+    Parses a comma separated list of trees:max_features:criterion forest settings, max_features may be
+    "auto" for the square root of the feature count
+
+    @param (list: &str) comma separated forest settings such as "100:auto:gini,50:2:entropy"
+    @param (base: &ForestOptions) forest options the settings are applied to
+
+    @return (CustomResult<Vec<ForestOptions>>) CustomResult containing one set of forest options per setting
+*/
+fn parse_grid(list: &str, base: &ForestOptions) -> CustomResult<Vec<ForestOptions>> {
+    let mut grid: Vec<ForestOptions> = Vec::new();
+
+    for setting in list.split(',') {
+        let parts: Vec<&str> = setting.trim().split(':').collect();
+        if parts.len() != 3 {
+            return Err(format!(
+                "invalid --grid setting \"{}\", expected trees:max_features:criterion",
+                setting
+            )
+            .into());
+        }
+
+        let trees: usize = match parts[0].parse() {
+            Ok(trees) if trees > 0 => trees,
+            _ => return Err(format!("invalid number of trees \"{}\" in --grid", parts[0]).into()),
+        };
+
+        let max_features: Option<usize> = match parts[1] {
+            "auto" => None,
+            value => match value.parse() {
+                Ok(max) if max > 0 && max <= base.features.len() => Some(max),
+                _ => {
+                    return Err(format!(
+                        "invalid max features \"{}\" in --grid, expected auto or 1 to {}",
+                        value,
+                        base.features.len()
+                    )
+                    .into())
+                }
+            },
+        };
+
+        let criterion: SplitCriterion = match parts[2] {
+            "gini" => SplitCriterion::Gini,
+            "entropy" => SplitCriterion::Entropy,
+            value => {
+                return Err(format!(
+                    "invalid criterion \"{}\" in --grid, expected gini or entropy",
+                    value
+                )
+                .into())
+            }
+        };
+
+        grid.push(ForestOptions {
+            trees,
+            max_features,
+            criterion,
+            ..base.clone()
+        });
+    }
+
+    Ok(grid)
+}
+
+/*
+    Cross-validates every forest setting of the grid and prints them sorted by mean accuracy. The random
+    forest crate has a fixed maximum tree depth, so the grid varies the max features of a split instead
+
+    @param (stocks: &[Stock]) vector of Stock objects parsed from the input file
+    @param (grid: &[ForestOptions]) forest settings to compare
+    @param (predict_index: usize) index of the stock to predict, only the stocks before it are used
+    @param (rng: &mut StdRng) random number generator of the folds and the forests
+
+    @return (CustomResult<()>) error of building the training table of a fold, if any
+*/
+fn print_grid(
+    stocks: &[Stock],
+    grid: &[ForestOptions],
+    predict_index: usize,
+    rng: &mut StdRng,
+) -> CustomResult<()> {
+    let mut rows: Vec<(&ForestOptions, f64, f64)> = Vec::new();
+    for options in grid {
+        let accuracies: Vec<f64> = cross_validate(stocks, options, predict_index, CV_FOLDS, rng)?
+            .iter()
+            .map(|accuracy| *accuracy as f64)
+            .collect();
+        rows.push((
+            options,
+            accuracies.iter().mean(),
+            accuracies.iter().population_std_dev(),
+        ));
+    }
+
+    rows.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    println!(
+        "{:>6} {:>12} {:>10} {:>17} {:>8}",
+        "Trees", "Max Features", "Criterion", "Mean CV Accuracy", "Std"
+    );
+    for (options, mean, std) in rows {
+        let max_features: String = options
+            .max_features
+            .map_or(String::from("auto"), |max| max.to_string());
+        println!(
+            "{:>6} {:>12} {:>10} {:>16.2}% {:>7.2}%",
+            options.trees,
+            max_features,
+            options.criterion.get_name(),
+            mean * 100.0,
+            std * 100.0
+        );
+    }
+
+    Ok(())
+}
+
+/*
+    Hashes the parsed rows and the settings which change the prediction with SHA-256, so a prediction can be
+    traced back to the exact data and options it came from. Only the settings of Config::prediction_settings are
+    hashed, as JSON, so the same file analyzed in another batch, with another verbosity or with a --report has the
+    same provenance
+
+    @param (stocks: &[Stock]) stocks as parsed from the input file
+    @param (config: &Config) config object constructed by the get_args function
+
+    @return (CustomResult<String>) CustomResult containing the hex encoded SHA-256 digest, or the error of
+    serializing the settings
+*/
+fn calculate_provenance(stocks: &[Stock], config: &Config) -> CustomResult<String> {
+    let mut hasher: Sha256 = Sha256::new();
+
+    for stock in stocks {
+        hasher.update(stock.get_date_string().as_bytes());
+        for value in stock.get_array() {
+            hasher.update(value.to_le_bytes());
+        }
+    }
+
+    hasher.update(serde_json::to_vec(&config.prediction_settings())?);
+
+    Ok(hex_digest(hasher))
+}
+
+/*
+    Serializes an analysis result to JSON text, reads it back and compares it with the original, so a field
+    which is not written or read back correctly is caught
+
+    @param (result: &AnalysisResult) analysis result to round trip
+
+    @return (CustomResult<()>) error naming the first field that changed, if any
+*/
+fn check_round_trip(result: &AnalysisResult) -> CustomResult<()> {
+    let text: String = result.to_json().to_string();
+    let reloaded: AnalysisResult = serde_json::from_str(&text)
+        .ok()
+        .and_then(|value| AnalysisResult::from_json(&value))
+        .ok_or("self-test: the JSON of the result could not be read back")?;
+
+    if reloaded != *result {
+        return Err(format!(
+            "self-test: the result changed in a JSON round trip\n{:?}\n{:?}",
+            result, reloaded
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/*
+    Finishes a SHA-256 hash and encodes it in hex
+
+    @param (hasher: Sha256) hasher which was given every byte to hash
+
+    @return (String) hex encoded SHA-256 digest
+*/
+fn hex_digest(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/*
+    Writes a JSON manifest with everything needed to repeat a run: the crate version, the resolved
+    arguments, which --from-repro reads back, the SHA-256 of every input file and the results
+
+    @param (path: &str) path of the JSON file, it is overwritten if it exists
+    @param (config: &Config) config object constructed by the get_args function
+    @param (results: &[AnalysisResult]) analysis results of the files that were processed
+
+    @return (CustomResult<()>) error of writing the file, if any
+*/
+fn write_manifest(path: &str, config: &Config, results: &[AnalysisResult]) -> CustomResult<()> {
+    let files: Vec<serde_json::Value> = config
+        .files
+        .iter()
+        .map(|file| {
+            // stdin cannot be read again to hash it
+            let sha256: Option<String> = fs::read(file).ok().map(|bytes| {
+                let mut hasher: Sha256 = Sha256::new();
+                hasher.update(bytes);
+                hex_digest(hasher)
+            });
+            json!({ "file": file, "sha256": sha256 })
+        })
+        .collect();
+
+    let manifest = json!({
+        "schema_version": JSON_SCHEMA_VERSION,
+        "version": env!("CARGO_PKG_VERSION"),
+        "arguments": config.arguments,
+        "config": config.prediction_settings(),
+        "files": files,
+        "results": results.iter().map(AnalysisResult::to_json).collect::<Vec<_>>(),
+    });
+    fs::write(path, serde_json::to_string_pretty(&manifest)?)?;
+
+    Ok(())
+}
+
+/*
+    Prints the mean and 90% confidence interval of the simulated price after every horizon
+
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths, at least as many days
+    long as the longest horizon
+    @param (horizons: &[usize]) numbers of days ahead, in increasing order
+    @param (money: &MoneyFormat) format of the prices
+*/
+fn print_horizon_sweep(price_paths: &[Vec<f64>], horizons: &[usize], money: &MoneyFormat) {
+    let rows: Vec<(usize, [String; 3])> = horizons
+        .iter()
+        .map(|horizon| {
+            let prices: &[f64] = &price_paths[*horizon];
+            (
+                *horizon,
+                [
+                    money.format(prices.iter().mean()),
+                    money.format(calculate_percentile(prices, 5.0)),
+                    money.format(calculate_percentile(prices, 95.0)),
+                ],
+            )
+        })
+        .collect();
+
+    let width: usize = rows
+        .iter()
+        .flat_map(|(_, prices)| prices.iter().map(String::len))
+        .chain(std::iter::once(15))
+        .max()
+        .unwrap();
+
+    println!(
+        "{:>7}  {:>width$}  {:>width$}  {:>width$}",
+        "Horizon", "Mean", "5th Percentile", "95th Percentile"
+    );
+    for (horizon, [mean, low, high]) in rows {
+        println!(
+            "{:>7}  {:>width$}  {:>width$}  {:>width$}",
+            horizon, mean, low, high
+        );
+    }
+}
+
+/*
+    Prints the mean and the 5th, 50th and 95th percentile of the first day the simulated paths reach a target
+    price, among the paths that reach it, and the share of the paths that never do within the horizon
+
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths
+    @param (target: f64) price the paths have to reach, set by --first-passage
+    @param (money: &MoneyFormat) format of the price and percentages
+*/
+fn print_first_passage(price_paths: &[Vec<f64>], target: f64, money: &MoneyFormat) {
+    let passages: Vec<Option<usize>> = first_passage_days(price_paths, target);
+    let days: Vec<f64> = passages.iter().flatten().map(|day| *day as f64).collect();
+    let never: f64 = 1.0 - days.len() as f64 / passages.len() as f64;
+
+    if days.is_empty() {
+        println!(
+            "No simulated path reaches {} within {} days",
+            money.format(target),
+            price_paths.len() - 1
+        );
+        return;
+    }
+
+    println!(
+        "The simulated price first reaches {} after {:.1} days on average (5th percentile {}, median {}, 95th percentile {})",
+        money.format(target),
+        days.iter().mean(),
+        calculate_percentile(&days, 5.0),
+        calculate_percentile(&days, 50.0),
+        calculate_percentile(&days, 95.0)
+    );
+    println!(
+        "{:.*}% of the paths never reach it within {} days",
+        money.decimals(),
+        never * 100.0,
+        price_paths.len() - 1
+    );
+}
+
+/*
+    Prints the annualized volatility implied by the simulated prices at every horizon, a term structure
+    which is flat under plain geometric Brownian motion
+
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths, at least as many days
+    long as the longest horizon
+    @param (horizons: &[usize]) numbers of days ahead, in increasing order
+    @param (periods_per_year: f64) number of data periods in one calendar year
+    @param (money: &MoneyFormat) format of the percentages
+*/
+fn print_vol_term_structure(
+    price_paths: &[Vec<f64>],
+    horizons: &[usize],
+    periods_per_year: f64,
+    money: &MoneyFormat,
+) {
+    println!("{:>7}  {:>10}", "Horizon", "Volatility");
+    for horizon in horizons {
+        let volatility: f64 = implied_volatility(price_paths, *horizon, periods_per_year);
+        println!(
+            "{:>7}  {:>9.*}%",
+            horizon,
+            money.decimals(),
+            volatility * 100.0
+        );
+    }
+}
+
+/*
+    Writes the 5th, 25th, 50th, 75th and 95th percentile of the simulated prices of every day to a CSV file,
+    the bands of a fan chart
+
+    @param (path: &str) path of the CSV file to create
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths
+
+    @return (CustomResult()) custom result object which indicates that the file was written
+*/
+fn write_fan_chart(path: &str, price_paths: &[Vec<f64>]) -> CustomResult<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["day", "p05", "p25", "p50", "p75", "p95"])?;
+
+    for (day, prices) in price_paths.iter().enumerate() {
+        let mut record: Vec<String> = vec![day.to_string()];
+        for percentile in [5.0, 25.0, 50.0, 75.0, 95.0] {
+            record.push(calculate_percentile(prices, percentile).to_string());
+        }
+        writer.write_record(&record)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/*
+    Writes the return series the simulation is estimated from as CSV with a date and a log_return or
+    simple_return column, each return is dated by the later of its two days
+
+    @param (path: &str) path of the CSV file, it is overwritten if it exists
+    @param (stocks: &[Stock]) vector of stock objects in date order
+    @param (returns: &[f64]) returns calculated by calculate_returns from the stocks
+    @param (return_type: ReturnType) whether the returns are logarithmic or simple, names the column
+
+    @return (CustomResult<()>) error of creating or writing the file, if any
+*/
+fn write_returns(
+    path: &str,
+    stocks: &[Stock],
+    returns: &[f64],
+    return_type: ReturnType,
+) -> CustomResult<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["date", &format!("{}_return", return_type.get_name())])?;
+
+    for (stock, period_return) in stocks[1..].iter().zip(returns) {
+        writer.write_record([stock.get_date_string(), period_return.to_string()])?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/*
+    Writes the rows the forests are trained on as CSV with a date column, one column per selected feature
+    and a label column, holding the same values construct_table adds to the training table. Every forest
+    trains on a random --train-frac split of these rows, repeated by --recency-weight if it is given
+
+    @param (path: &str) path of the CSV file, it is overwritten if it exists
+    @param (stocks: &[Stock]) labeled stocks before the predicted one, after every feature transform
+    @param (features: &[usize]) indices of the features the forests are trained on
+
+    @return (CustomResult<()>) error of creating or writing the file, if any
+*/
+fn write_training_table(path: &str, stocks: &[Stock], features: &[usize]) -> CustomResult<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+
+    let mut header: Vec<&str> = vec!["date"];
+    header.extend(features.iter().map(|idx| feature_name(*idx)));
+    header.push("label");
+    writer.write_record(&header)?;
+
+    for stock in stocks {
+        let mut record: Vec<String> = vec![stock.get_date_string()];
+        record.extend(
+            stock
+                .feature_subset(features)
+                .iter()
+                .map(|value| value.to_string()),
+        );
+        record.push(stock.get_label().to_string());
+        writer.write_record(&record)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/*
+    Writes the beta of every rolling window as CSV with a date and a beta column, each window dated by its
+    last day
+
+    @param (path: &str) path of the CSV file, it is overwritten if it exists
+    @param (series: &[(String, f64)]) dates and betas calculated by calculate_rolling_beta
+
+    @return (CustomResult<()>) error of creating or writing the file, if any
+*/
+fn write_rolling_beta(path: &str, series: &[(String, f64)]) -> CustomResult<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["date", "beta"])?;
+
+    for (date, beta) in series {
+        writer.write_record([date.clone(), beta.to_string()])?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/*
+    Prints the first complete random walks to stderr, one line per walk from the current price to the
+    final simulated day, to check that the paths start at the right price and evolve plausibly
+
+    @param (price_paths: &[Vec<f64>]) price paths calculated by calculate_price_paths
+    @param (count: usize) number of random walks to print
+*/
+fn print_debug_paths(price_paths: &[Vec<f64>], count: usize) {
+    for trial in 0..count.min(price_paths[0].len()) {
+        let prices: Vec<String> = price_paths
+            .iter()
+            .map(|day| format!("{:.2}", day[trial]))
+            .collect();
+        eprintln!("Path {}: {}", trial + 1, prices.join(" -> "));
+    }
+}
+
+/*
+    Parses stock data with a header row followed by one Date,Open,High,Low,Close,Adj Close,Volume row per
+    day, then labels every stock with tomorrow's direction and sets its logarithmic return. A row with too
+    few fields or a field that is not a number is skipped with a warning naming its line, unless the config
+    is strict
+
+    @param (reader: R) buffered reader over the data, e.g. a file, stdin or a Cursor<&str>
+    @param (cfg: &ParseConfig) delimiter and number format of the data
+
+    @return (CustomResult<Vec<Stock>>) CustomResult containing the stocks in the order they were read, or an
+    error naming the first row that could not be read, or with --strict the first row that could not be parsed
+*/
+pub fn parse_stocks<R: BufRead>(reader: R, cfg: &ParseConfig) -> CustomResult<Vec<Stock>> {
+    let mut stock_vec: Vec<Stock> = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line: String = line?;
+        // a stray \r of mixed line endings or a whitespace-only line counts as empty
+        let line: &str = line.trim();
+        if line_number == 0 || line.is_empty() {
+            continue;
+        }
+
+        let stock: Stock = match parse_row(line, cfg) {
+            Ok(stock) => stock,
+            Err(e) if cfg.strict => return Err(format!("line {}: {}", line_number + 1, e).into()),
+            Err(e) => {
+                warn!("line {}: {}, the row is skipped", line_number + 1, e);
+                continue;
+            }
+        };
+        if cfg.strict {
+            stock
+                .validate()
+                .map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+        }
+        stock_vec.push(stock);
+    }
+
+    label_stocks(&mut stock_vec, cfg.return_type);
+
+    Ok(stock_vec)
+}
+
+/*
+    Parses one Date,Open,High,Low,Close,Adj Close,Volume row into an unlabeled stock
+
+    @param (line: &str) trimmed line of the data
+    @param (cfg: &ParseConfig) delimiter and number format of the data
+
+    @return (Result<Stock, String>) the stock, or what is wrong with the row
+*/
+fn parse_row(line: &str, cfg: &ParseConfig) -> Result<Stock, String> {
+    let line_vec: Vec<String> = line
+        .split(cfg.delimiter)
+        .enumerate()
+        .map(|(column, field)| {
+            // the date column is not a number
+            normalize_number(field, cfg.decimal_comma && column > 0)
+        })
+        .collect();
+    if line_vec.len() < 7 {
+        return Err(format!("{} fields, expected 7", line_vec.len()));
+    }
+
+    let prices: Vec<f64> = line_vec[1..6]
+        .iter()
+        .map(|field| field.parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("invalid price: {}", e))?;
+    let volume: u64 = parse_volume(&line_vec[6]).map_err(|e| format!("invalid volume: {}", e))?;
+
+    Ok(Stock::new(
+        line_vec[0].clone(),
+        prices[0],
+        prices[1],
+        prices[2],
+        prices[3],
+        prices[4],
+        volume,
+        Tomorrow::Predict,
+    ))
+}
+
+/*
+    Parses every field of the stock data like parse_stocks does, but instead of stopping at the first bad row
+    counts the fields of each column that parse and those that do not
+
+    @param (reader: R) buffered reader over the data, e.g. a file, stdin or a Cursor<&str>
+    @param (cfg: &ParseConfig) delimiter and number format of the data
+
+    @return (CustomResult<Vec<ColumnStats>>) CustomResult containing the statistics of the seven columns in file
+    order, or the error of reading the data
+*/
+pub fn column_stats<R: BufRead>(reader: R, cfg: &ParseConfig) -> CustomResult<Vec<ColumnStats>> {
+    let mut stats: Vec<ColumnStats> = [
+        "date",
+        "open",
+        "high",
+        "low",
+        "close",
+        "adj_close",
+        "volume",
+    ]
+    .iter()
+    .map(|name| ColumnStats {
+        name,
+        parsed: 0,
+        failed: 0,
+        first_failure: None,
+    })
+    .collect();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line: String = line?;
+        // a stray \r of mixed line endings or a whitespace-only line counts as empty
+        let line: &str = line.trim();
+        if line_number == 0 || line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(cfg.delimiter).collect();
+        for (column, stat) in stats.iter_mut().enumerate() {
+            let field: Option<&str> = fields.get(column).copied();
+            let parsed: bool = match field {
+                None => false,
+                Some(field) if column == 0 => parse_date(field).is_ok(),
+                Some(field) if column == 6 => {
+                    parse_volume(&normalize_number(field, cfg.decimal_comma)).is_ok()
+                }
+                Some(field) => normalize_number(field, cfg.decimal_comma)
+                    .parse::<f64>()
+                    .is_ok(),
+            };
+
+            if parsed {
+                stat.parsed += 1;
+            } else {
+                stat.failed += 1;
+                if stat.first_failure.is_none() {
+                    let example: String = field.map_or(String::from("<missing>"), String::from);
+                    stat.first_failure = Some((line_number + 1, example));
+                }
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/*
+    Prints, for every forest of the vote, how many of its training rows each tree is expected to see and to
+    leave out of its bootstrap sample. Few rows seen explain why forests of tiny datasets disagree
+
+    @param (forests: &[TrainedForest]) forests of the vote
+*/
+fn print_out_of_bag(forests: &[TrainedForest]) {
+    for (idx, forest) in forests.iter().enumerate() {
+        match forest.get_out_of_bag() {
+            Some((rows, out_of_bag)) => println!(
+                "Forest {}: each tree sees about {:.1} of {} training rows, {:.1} ({:.1}%) are out of bag",
+                idx + 1,
+                rows as f64 - out_of_bag,
+                rows,
+                out_of_bag,
+                out_of_bag / rows as f64 * 100.0
+            ),
+            None => println!(
+                "Forest {}: loaded from a model file, its training rows are unknown",
+                idx + 1
+            ),
+        }
+    }
+}
+
+/*
+    Prints one JSON line for every forest of the vote with its iteration, predicted direction, test accuracy and
+    whether it was inverted, so the spread of the forests can be studied instead of only their average
+
+    @param (filename: &str) path of the analyzed file
+    @param (forests: &[TrainedForest]) forests of the vote
+    @param (stock: &Stock) stock the forests predict
+    @param (features: &[usize]) indices of the features the forests were trained on
+*/
+fn print_forest_stats(
+    filename: &str,
+    forests: &[TrainedForest],
+    stock: &Stock,
+    features: &[usize],
+) {
+    for (iteration, trained) in forests.iter().enumerate() {
+        let forest: ForestResult = trained.predict(stock, features);
+        let direction: Direction = if Tomorrow::from_label(forest.prediction)
+            .is_some_and(|tomorrow| tomorrow.is_increase())
+        {
+            Direction::Increase
+        } else {
+            Direction::Decrease
+        };
+
+        let record: Value = json!({
+            "schema_version": JSON_SCHEMA_VERSION,
+            "file": filename,
+            "iteration": iteration,
+            "direction": direction.get_name(),
+            "probability": forest.probability,
+            "accuracy": forest.accuracy,
+            "inverted": forest.inverted,
+        });
+        println!("{}", record);
+    }
+}
+
+/*
+    Formats example command lines, taking the long name of every flag from the parsed command so the
+    examples follow a renamed flag
+
+    @param (command: &Command) command the arguments are parsed with
+
+    @return (String) a description followed by its indented command line for every example
+*/
+fn format_examples(command: &Command) -> String {
+    let flag = |id: &str| {
+        let long: &str = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id)
+            .and_then(|arg| arg.get_long())
+            .unwrap_or_else(|| panic!("--help-examples uses {}, which is not an argument", id));
+        format!("--{}", long)
+    };
+
+    let one: &str = "tests/inputs/Amazon.csv";
+    let batch: &str = "tests/inputs/Amazon.csv tests/inputs/Tesla.csv";
+    let examples: Vec<(&str, Vec<String>, &str)> = vec![
+        (
+            "Forecast the price and the direction of one stock",
+            vec![],
+            one,
+        ),
+        (
+            "Compare a batch of stocks in one table",
+            vec![flag("format"), String::from("table")],
+            batch,
+        ),
+        (
+            "Print the predictions of a batch as one JSON object per file",
+            vec![flag("format"), String::from("json")],
+            batch,
+        ),
+        (
+            "Print the drift and volatility of a batch as one JSON line per file",
+            vec![flag("params_only"), flag("metrics")],
+            batch,
+        ),
+        (
+            "Forecast with a fixed seed, so running it again gives the same output",
+            vec![flag("seed"), String::from("42")],
+            one,
+        ),
+        (
+            "Record the settings of a run in a manifest",
+            vec![flag("repro_out"), String::from("run.json")],
+            one,
+        ),
+        (
+            "Replay the recorded run",
+            vec![flag("from_repro"), String::from("run.json")],
+            "",
+        ),
+        (
+            "Forecast several horizons and the chance of ending above a price",
+            vec![
+                flag("horizon_sweep"),
+                String::from("5,20,60"),
+                flag("prob_above"),
+                String::from("150"),
+            ],
+            one,
+        ),
+        (
+            "Train the forests once and save them",
+            vec![flag("save_model"), String::from("amazon.model")],
+            one,
+        ),
+        (
+            "Predict with the saved forests",
+            vec![flag("load_model"), String::from("amazon.model")],
+            one,
+        ),
+    ];
+
+    let mut text: String = String::new();
+    for (description, flags, files) in examples {
+        let mut line: Vec<&str> = vec!["cargo run --"];
+        line.extend(flags.iter().map(String::as_str));
+        if !files.is_empty() {
+            line.push(files);
+        }
+
+        text.push_str(&format!("{}:\n    {}\n\n", description, line.join(" ")));
+    }
+
+    text
+}
+
+/*
+    Formats the features the random forest can use as a table with their source columns, marking the ones
+    selected by --features and --engineered-features as active
+
+    @param (config: &Config) config object constructed by the get_args function
+
+    @return (String) table with one row per feature in the order of FEATURE_NAMES and ENGINEERED_FEATURE_NAMES
+*/
+fn format_feature_list(config: &Config) -> String {
+    let volume: String = match (config.volume_buckets, config.volume_transform) {
+        (Some(buckets), _) => format!("Volume, bucket out of {}", buckets),
+        (None, VolumeTransform::None) => String::from("Volume"),
+        (None, transform) => format!("Volume, {} transform", transform.get_name()),
+    };
+    let sources: [String; 9] = [
+        String::from("Open"),
+        String::from("High"),
+        String::from("Low"),
+        String::from("Adj Close"),
+        String::from("Close"),
+        volume,
+        format!(
+            "Close, {} over {} row moving average",
+            SMA_SHORT_WINDOW, SMA_LONG_WINDOW
+        ),
+        format!("Close, {} row RSI", RSI_WINDOW),
+        String::from("Date"),
+    ];
+
+    let mut table: String = format!(
+        "{:<5}  {:<11}  {:<6}  Source\n",
+        "Index", "Feature", "Active"
+    );
+    for (idx, source) in sources.iter().enumerate() {
+        let active: bool = if idx < FEATURE_NAMES.len() {
+            config.forest.features.contains(&idx)
+        } else {
+            config.engineered_features
+        };
+        table.push_str(&format!(
+            "{:<5}  {:<11}  {:<6}  {}\n",
+            idx,
+            feature_name(idx),
+            if active { "yes" } else { "no" },
+            source
+        ));
+    }
+
+    table
+}
+
+/*
+    Formats the statistics of column_stats as a table with one row per column
+
+    @param (filename: &str) path of the file the statistics are of
+    @param (stats: &[ColumnStats]) statistics returned by column_stats
+
+    @return (String) title line followed by the table
+*/
+fn format_column_stats(filename: &str, stats: &[ColumnStats]) -> String {
+    let mut table: String = format!(
+        "{}:\n{:<10}  {:>8}  {:>8}  First failure\n",
+        filename, "Column", "Parsed", "Failed"
+    );
+
+    for stat in stats {
+        let first_failure: String = match &stat.first_failure {
+            Some((line, field)) => format!("line {}: \"{}\"", line, field),
+            None => String::new(),
+        };
+        let row: String = format!(
+            "{:<10}  {:>8}  {:>8}  {}",
+            stat.name, stat.parsed, stat.failed, first_failure
+        );
+        table.push_str(row.trim_end());
+        table.push('\n');
+    }
+
+    table
+}
+
+/*
+    Parses a share volume, which some sources write as a float such as 1234567.0 or 1.2e7
+
+    @param (field: &str) volume field of a data row
+
+    @return (Result<u64, String>) the volume, or why it is not a whole non-negative number
+*/
+fn parse_volume(field: &str) -> Result<u64, String> {
+    if let Ok(volume) = field.parse::<u64>() {
+        return Ok(volume);
+    }
+
+    let volume: f64 = field.parse().map_err(|e| format!("{}", e))?;
+    if volume < 0.0 {
+        Err(format!("{} is negative", field))
+    } else if volume.fract() != 0.0 || volume > u64::MAX as f64 {
+        Err(format!("{} is not a whole number of shares", field))
+    } else {
+        Ok(volume as u64)
+    }
+}
+
+/*
+    Rewrites a number written with a decimal comma such as 1.234,56 into the 1234.56 form f64::parse
+    expects, fields are left untouched when decimal commas are not used
+
+    @param (field: &str) field of a data row
+    @param (decimal_comma: bool) whether the file uses commas as decimal separators and dots between thousands
+
+    @return (String) field ready to be parsed
+*/
+fn normalize_number(field: &str, decimal_comma: bool) -> String {
+    if decimal_comma {
+        field.replace('.', "").replace(',', ".")
+    } else {
+        String::from(field)
+    }
+}
+
+/*
+    Opens a passed file which is in respect to the current working directory, "-" reads stdin unless it is an
+    interactive terminal, where reading would silently wait for typed data
+
+    @param (filename: &str) relative file path which is used to open the stock data file
+
+    @return (CustomResult<Box<dyn BufRead>>) CustomResult containing BufRead object used to read the passed file
+*/
+fn open_file(filename: &str) -> CustomResult<Box<dyn BufRead>> {
+    match filename {
+        "-" if io::stdin().is_terminal() => {
+            Err("reading from stdin; pipe data in or pass a file, see --help".into())
+        }
+        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
+        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+    }
+}
+
+/*
+    Parses the command line argument including the filepaths and the number of prediction days
+
+    @return (CustomResult<Config>) CustomResult containing Config object holding passed arguments
+*/
+pub fn get_args() -> CustomResult<Config> {
+    let mut args: Vec<String> = env::args().collect();
+    // a manifest replays its recorded arguments, the other flags on the command line still override them
+    if let Some(path) = flag_value(&args, "--from-repro") {
+        let recorded: Vec<String> = load_repro_arguments(&path)?;
+        let given: Vec<String> = without_flag(&args[1..], "--from-repro");
+        args.truncate(1);
+        args.extend(recorded);
+        args.extend(given);
+    }
+    // the settings of a --config file go first so the flags on the command line override them
+    if let Some(path) = flag_value(&args, "--config") {
+        let settings: Settings = Settings::load(&path)?;
+        args.splice(1..1, settings.to_args());
+    }
+    let mut arguments: Vec<String> =
+        without_flag(&without_flag(&args[1..], "--config"), "--repro-out");
+
+    let command: Command = Command::new("rusty_stocks")
+        .version("0.1.0")
+        .author("Derek Warner <derekw3@illinois.edu>, Chengxun Ren <cren8@illinois.edu>, Haozhe Chen <haozhe6@illinois.edu>, Aaryan Singh Gusain <agusain2@illinois.edu>")
+        .about("A CLI stock prediction application")
+        .args_override_self(true)
+        .arg(
+            Arg::new("files")
+                .help("Input File(s)")
+                .default_value("-")
+                .num_args(1..),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("TOML")
+                .help("Read default settings from a TOML file, keys are the long flags with underscores"),
+        )
+        .arg(
+            Arg::new("repro_out")
+                .long("repro-out")
+                .value_name("JSON")
+                .help("Write the version, arguments, input file hashes and results of the run to a JSON manifest"),
+        )
+        .arg(
+            Arg::new("from_repro")
+                .long("from-repro")
+                .value_name("JSON")
+                .help("Repeat the run recorded in a --repro-out manifest"),
+        )
+        .arg(
+            Arg::new("glob")
+                .long("glob")
+                .value_name("PATTERN")
+                .help("Also analyze every file matching PATTERN, e.g. \"data/*.csv\", may be repeated")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Reject files with malformed rows, which are skipped otherwise, or with rows whose high is below the low or whose open or close is outside of them")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("return_type")
+                .long("return-type")
+                .value_name("TYPE")
+                .help("Compute the returns of the drift, volatility, beta and --returns-out as log or simple returns")
+                .value_parser(["log", "simple"])
+                .default_value("log"),
+        )
+        .arg(
+            Arg::new("validate")
+                .long("validate")
+                .help("Only count the fields of every column that parse and print the first failure of each, instead of analyzing")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("target_date")
+                .long("target-date")
+                .value_name("YYYY-MM-DD")
+                .help("Run the simulation up to this date instead of a fixed number of days"),
+        )
+        .arg(
+            Arg::new("holidays")
+                .long("holidays")
+                .value_name("FILE")
+                .help("File of market holidays, one YYYY-MM-DD per line, skipped with weekends when daily horizons are mapped to dates"),
+        )
+        .arg(
+            Arg::new("days")
+                .long("days")
+                .value_name("N")
+                .help("Number of days ahead the price is forecast [default: 29]")
+                .value_parser(value_parser!(usize))
+                .conflicts_with("target_date"),
+        )
+        .arg(
+            Arg::new("trials")
+                .long("trials")
+                .value_name("N")
+                .help("Number of Monte Carlo random walks, fewer run faster and use less memory but give noisier forecasts [default: 50000]")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("N")
+                .help("Seed the random splits, forests and random walks so a run can be reproduced exactly, without it a seed is drawn and recorded by --repro-out")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .value_name("YYYY-MM-DD")
+                .help("Only use the rows dated on or after this date"),
+        )
+        .arg(
+            Arg::new("until")
+                .long("until")
+                .value_name("YYYY-MM-DD")
+                .help("Only use the rows dated on or before this date"),
+        )
+        .arg(
+            Arg::new("periods_per_year")
+                .long("periods-per-year")
+                .value_name("N")
+                .help("Number of data periods in a year, used to convert dates into periods [default: from --period]")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("period")
+                .long("period")
+                .value_name("LABEL")
+                .help("Length of one row of data, intraday rows may have datetimes")
+                .value_parser(["1h", "1d", "1wk", "1mo"])
+                .default_value("1d"),
+        )
+        .arg(
+            Arg::new("features")
+                .long("features")
+                .value_name("LIST")
+                .help("Comma separated features used by the random forest")
+                .default_value("open,high,low,adj_close,close,volume"),
+        )
+        .arg(
+            Arg::new("chunk_size")
+                .long("chunk-size")
+                .value_name("N")
+                .help("Simulate the Monte Carlo trials N at a time to bound memory use")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .value_name("N")
+                .help("Run the parallel Monte Carlo simulation on at most N threads [default: one per core]")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("debug_paths")
+                .long("debug-paths")
+                .value_name("N")
+                .help("Print the first N complete Monte Carlo price paths to stderr")
+                .value_parser(value_parser!(usize))
+                .default_value("0")
+                .conflicts_with("chunk_size"),
+        )
+        .arg(
+            Arg::new("fan_out")
+                .long("fan-out")
+                .value_name("CSV")
+                .help("Write the 5th, 25th, 50th, 75th and 95th percentile price of every simulated day to CSV")
+                .conflicts_with("chunk_size"),
+        )
+        .arg(
+            Arg::new("returns_out")
+                .long("returns-out")
+                .value_name("CSV")
+                .help("Write the date and return of every row after the first to CSV, see --return-type"),
+        )
+        .arg(
+            Arg::new("dump_table")
+                .long("dump-table")
+                .value_name("CSV")
+                .help("Write the date, selected features and label of every row the forests are trained on to CSV")
+                .conflicts_with("load_model"),
+        )
+        .arg(
+            Arg::new("plot_out")
+                .long("plot-out")
+                .value_name("PNG")
+                .help("Draw the close prices, the median forecast and its 5% to 95% band to PNG (needs the plot feature)")
+                .conflicts_with("chunk_size"),
+        )
+        .arg(
+            Arg::new("warmup")
+                .long("warmup")
+                .value_name("W")
+                .help("Report the prices of every simulated day from day W on instead of only the last day")
+                .value_parser(value_parser!(usize))
+                .conflicts_with("chunk_size"),
+        )
+        .arg(
+            Arg::new("horizon_sweep")
+                .long("horizon-sweep")
+                .value_name("LIST")
+                .help("Comma separated horizons in days, e.g. 5,10,20, to tabulate the forecast at")
+                .value_parser(value_parser!(usize))
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("vol_term_structure")
+                .long("vol-term-structure")
+                .help("Print the annualized volatility of the simulated log returns at every --horizon-sweep horizon")
+                .action(ArgAction::SetTrue)
+                .requires("horizon_sweep"),
+        )
+        .arg(
+            Arg::new("discount_rate")
+                .long("discount-rate")
+                .value_name("RATE")
+                .help("Average the expected prices of the --horizon-sweep discounted at this annualized continuously compounded rate")
+                .value_parser(value_parser!(f64))
+                .requires("horizon_sweep"),
+        )
+        .arg(
+            Arg::new("save_model")
+                .long("save-model")
+                .value_name("PATH")
+                .help("Save the trained random forests to PATH for --load-model"),
+        )
+        .arg(
+            Arg::new("load_model")
+                .long("load-model")
+                .value_name("PATH")
+                .help("Predict with the random forests saved in PATH instead of training new ones"),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .value_name("PATH")
+                .help("Write a Markdown report of the analysis to this file"),
+        )
+        .arg(
+            Arg::new("predict_index")
+                .long("predict-index")
+                .value_name("ROW")
+                .help("Zero based data row the random forest predicts, defaults to the last row")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("merge_mode")
+                .long("merge-mode")
+                .value_name("MODE")
+                .help("Join the input files into one series, concat appends the rows and chain scales every file to continue the one before it")
+                .value_parser(["concat", "chain"])
+                .conflicts_with("validate"),
+        )
+        .arg(
+            Arg::new("dup_policy")
+                .long("dup-policy")
+                .value_name("POLICY")
+                .help("How rows of the same date are reconciled: keep the first or last, average them, or reject the file [default: error with --strict, last otherwise]")
+                .value_parser(["first", "last", "average", "error"]),
+        )
+        .arg(
+            Arg::new("assert_accuracy")
+                .long("assert-accuracy")
+                .value_name("PCT")
+                .help("Fail a file whose average test accuracy, before inverting inaccurate forests, is below PCT percent")
+                .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            Arg::new("show_oob")
+                .long("show-oob")
+                .help("Print how many training rows each tree of every forest is expected to see and leave out of its bootstrap sample")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("list_features")
+                .long("list-features")
+                .help("Print the features of the random forest, their source columns and which are active, then exit")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("print_config")
+                .long("print-config")
+                .help("Print the settings resolved from the defaults, the --config file and the flags to stderr, then continue")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("help_examples")
+                .long("help-examples")
+                .help("Print example command lines, then exit")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("self_test")
+                .long("self-test")
+                .help("Check that every result is read back unchanged from its JSON")
+                .action(ArgAction::SetTrue)
+                .hide(true),
+        )
+        .arg(
+            Arg::new("params_only")
+                .long("params-only")
+                .help("Only estimate the drift and volatility of each file and print them as JSON")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("risk_neutral")
+                .long("risk-neutral")
+                .help("Simulate with the risk free rate as the drift instead of the historical mean return")
+                .action(ArgAction::SetTrue)
+                .requires("rate"),
+        )
+        .arg(
+            Arg::new("rate")
+                .long("rate")
+                .value_name("RATE")
+                .help("Annualized continuously compounded risk free rate used by --risk-neutral, e.g. 0.05")
+                .value_parser(value_parser!(f64))
+                .requires("risk_neutral"),
+        )
+        .arg(
+            Arg::new("start_price")
+                .long("start-price")
+                .value_name("PRICE")
+                .help("Start the Monte Carlo simulation from a hypothetical price, the drift and volatility are still estimated from the data")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("prob_above")
+                .long("prob-above")
+                .value_name("PRICE")
+                .help("Print the fraction of the simulated prices above PRICE as a probability")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("prob_below")
+                .long("prob-below")
+                .value_name("PRICE")
+                .help("Print the fraction of the simulated prices below PRICE as a probability")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("first_passage")
+                .long("first-passage")
+                .value_name("PRICE")
+                .help("Print the distribution of the first simulated day the price reaches PRICE and how many paths never do")
+                .value_parser(value_parser!(f64))
+                .conflicts_with("chunk_size"),
+        )
+        .arg(
+            Arg::new("benchmark_file")
+                .long("benchmark-file")
+                .value_name("FILE")
+                .help("Report the beta and alpha of the data against a benchmark series such as an index, joined by date"),
+        )
+        .arg(
+            Arg::new("rolling_beta")
+                .long("rolling-beta")
+                .value_name("WINDOW")
+                .help("Calculate the beta against the benchmark over every window of WINDOW returns and write it to --rolling-beta-out")
+                .value_parser(value_parser!(usize))
+                .requires_all(["benchmark_file", "rolling_beta_out"]),
+        )
+        .arg(
+            Arg::new("rolling_beta_out")
+                .long("rolling-beta-out")
+                .value_name("CSV")
+                .help("Write the date and beta of every rolling window to CSV")
+                .requires("rolling_beta"),
+        )
+        .arg(
+            Arg::new("volume_buckets")
+                .long("volume-buckets")
+                .value_name("N")
+                .help("Replace the volume feature with its bucket out of N over a trailing window")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("volume_transform")
+                .long("volume-transform")
+                .value_name("TRANSFORM")
+                .help("Replace the volume feature with ln(1 + volume) or its percentile rank among the training volumes, and report the accuracy of each transform")
+                .value_parser(["none", "log", "rank"])
+                .default_value("none")
+                .conflicts_with("volume_buckets"),
+        )
+        .arg(
+            Arg::new("stale_threshold")
+                .long("stale-threshold")
+                .value_name("K")
+                .help("Warn about runs of at least K rows with identical open, high, low and close")
+                .value_parser(value_parser!(usize))
+                .default_value("5"),
+        )
+        .arg(
+            Arg::new("detect_splits")
+                .long("detect-splits")
+                .value_name("THRESHOLD")
+                .help("Warn about days where the close and adj_close ratios differ by more than THRESHOLD")
+                .value_parser(value_parser!(f64))
+                .num_args(0..=1)
+                .default_missing_value("0.2"),
+        )
+        .arg(
+            Arg::new("adjust_splits")
+                .long("adjust-splits")
+                .help("Adjust every price by adj_close when --detect-splits finds a split")
+                .action(ArgAction::SetTrue)
+                .requires("detect_splits"),
+        )
+        .arg(
+            Arg::new("engineered_features")
+                .long("engineered-features")
+                .help("Add a short/long SMA ratio, RSI and day of the week to the random forest features")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no_invert")
+                .long("no-invert")
+                .help("Report the raw random forest instead of flipping forests with less than 50% accuracy")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("bootstrap_returns")
+                .long("bootstrap-returns")
+                .help("Simulate by resampling the historical daily returns instead of normal returns")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("grid")
+                .long("grid")
+                .value_name("LIST")
+                .help("Cross-validate forest settings given as trees:max_features:criterion, e.g. 100:auto:gini,50:2:entropy"),
+        )
+        .arg(
+            Arg::new("delimiter")
+                .long("delimiter")
+                .value_name("CHAR")
+                .help("Character separating the columns of the input files")
+                .value_parser(value_parser!(char))
+                .default_value(","),
+        )
+        .arg(
+            Arg::new("decimal_comma")
+                .long("decimal-comma")
+                .help("Read numbers written like 1.234,56 with a decimal comma, needs another --delimiter")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("weighted_vote")
+                .long("weighted-vote")
+                .help("Weight the vote of every forest by its test accuracy instead of counting each forest once")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("prob_threshold")
+                .long("prob-threshold")
+                .value_name("P")
+                .help("Only call an increase (decrease) when the forest probability is at least P (at most 1 - P)")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Print the predictions as text sentences, as one aligned table, as one JSON object per file or, with ndjson-stats, one JSON line per forest of the vote")
+                .value_parser(["text", "table", "json", "ndjson-stats"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::new("train_frac")
+                .long("train-frac")
+                .value_name("FRACTION")
+                .help("Fraction of the data the random forest is trained on, the rest is used for testing")
+                .value_parser(value_parser!(f32))
+                .default_value("0.9"),
+        )
+        .arg(
+            Arg::new("forest_max_votes")
+                .long("forest-max-votes")
+                .value_name("N")
+                .help("Most random forests trained to vote on each prediction [default: 10]")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("meta_forests")
+                .long("meta-forests")
+                .value_name("N")
+                .help("Train exactly N independent forests, take the majority of their majority votes and print the share agreeing with it")
+                .value_parser(value_parser!(usize))
+                .conflicts_with_all(["forest_max_votes", "forest_target_stability"]),
+        )
+        .arg(
+            Arg::new("forest_target_stability")
+                .long("forest-target-stability")
+                .value_name("EPS")
+                .help("Stop training forests once their average test accuracy changes by less than EPS")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("recency_weight")
+                .long("recency-weight")
+                .value_name("LAMBDA")
+                .help("Weight training rows by exp(-LAMBDA * age in rows), approximated by repeating recent rows")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("group_by_direction")
+                .long("group-by-direction")
+                .help("Once every file is analyzed, print the results grouped by predicted direction")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("explain_prediction")
+                .long("explain-prediction")
+                .help("Print which features pushed the forests toward the predicted direction the most")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("metrics")
+                .long("metrics")
+                .help("Print diagnostics of the model assumptions, such as the lag-1 autocorrelation of the returns")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("provenance")
+                .long("provenance")
+                .help("Print the SHA-256 hash of each file's parsed rows and the settings")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("currency")
+                .long("currency")
+                .value_name("SYMBOL")
+                .help("Currency symbol written before prices in text, table and report output")
+                .default_value(""),
+        )
+        .arg(
+            Arg::new("precision")
+                .long("precision")
+                .value_name("DIGITS")
+                .help("Decimals of the prices and percentages in the text and table output [default: 2 for prices, percentages unrounded]")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("thousands")
+                .long("thousands")
+                .help("Separate thousands in prices with commas")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .value_name("WHEN")
+                .help("Color increases green and decreases red, auto colors a terminal unless NO_COLOR is set [default: auto]")
+                .value_parser(["auto", "always", "never"]),
+        )
+        .arg(
+            Arg::new("no_color")
+                .long("no-color")
+                .help("Same as --color never")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("color"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .short('v')
+                .help("Print debugging information while analyzing")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("quiet"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .long("quiet")
+                .short('q')
+                .help("Only print the predictions and errors")
+                .action(ArgAction::SetTrue),
+        )
+;
+    let mut matches = command.clone().get_matches_from(args);
+
+    // stdin is only read by default when no --glob is given either
+    let mut files_vec: Vec<String> = if matches.value_source("files")
+        == Some(ValueSource::DefaultValue)
+        && matches.contains_id("glob")
+    {
+        Vec::new()
+    } else {
+        matches.remove_many("files").unwrap().collect()
+    };
+
+    let mut glob_matches: Vec<(String, usize)> = Vec::new();
+    for pattern in matches.remove_many::<String>("glob").into_iter().flatten() {
+        let mut matched: usize = 0;
+        for path in glob::glob(&pattern)? {
+            files_vec.push(path?.to_string_lossy().into_owned());
+            matched += 1;
+        }
+        glob_matches.push((pattern, matched));
+    }
+    if files_vec.is_empty() {
+        return Err("no input files, every --glob pattern matched nothing".into());
+    }
+
+    let mut horizon_sweep: Vec<usize> = matches
+        .remove_many("horizon_sweep")
+        .map_or(Vec::new(), |horizons| horizons.collect());
+    if horizon_sweep.contains(&0) {
+        return Err("--horizon-sweep horizons must be at least 1 day".into());
+    }
+    horizon_sweep.sort_unstable();
+    horizon_sweep.dedup();
+
+    let merge_mode: Option<MergeMode> = match matches.remove_one::<String>("merge_mode").as_deref()
+    {
+        Some(_) if files_vec.len() < 2 => {
+            return Err("--merge-mode needs at least two input files".into())
+        }
+        Some("chain") => Some(MergeMode::Chain),
+        Some(_) => Some(MergeMode::Concat),
+        None => None,
+    };
+    // merged files are analyzed as a single input
+    let several_inputs: bool = files_vec.len() > 1 && merge_mode.is_none();
+
+    let save_model: Option<String> = matches.remove_one("save_model");
+    let load_model: Option<String> = matches.remove_one("load_model");
+    if (save_model.is_some() || load_model.is_some()) && several_inputs {
+        return Err("--save-model and --load-model take a single input file".into());
+    }
+
+    let fan_out: Option<String> = matches.remove_one("fan_out");
+    if fan_out.is_some() && several_inputs {
+        return Err("--fan-out takes a single input file".into());
+    }
+
+    let returns_out: Option<String> = matches.remove_one("returns_out");
+    if returns_out.is_some() && several_inputs {
+        return Err("--returns-out takes a single input file".into());
+    }
+
+    let dump_table: Option<String> = matches.remove_one("dump_table");
+    if dump_table.is_some() && several_inputs {
+        return Err("--dump-table takes a single input file".into());
+    }
+
+    let rolling_beta: Option<(usize, String)> = match matches.remove_one::<usize>("rolling_beta") {
+        Some(window) if window < 2 => {
+            return Err("--rolling-beta needs a window of at least 2 returns".into())
+        }
+        Some(_) if several_inputs => {
+            return Err("--rolling-beta-out takes a single input file".into())
+        }
+        Some(window) => Some((window, matches.remove_one("rolling_beta_out").unwrap())),
+        None => None,
+    };
+
+    let plot_out: Option<String> = matches.remove_one("plot_out");
+    if plot_out.is_some() && several_inputs {
+        return Err("--plot-out takes a single input file".into());
+    }
+    #[cfg(not(feature = "plot"))]
+    if plot_out.is_some() {
+        return Err(
+            "--plot-out needs the plot feature, build without --no-default-features".into(),
+        );
+    }
+
+    let target_date: Option<NaiveDate> =
+        parse_date_flag("--target-date", matches.remove_one("target_date"))?;
+    let calendar: TradingCalendar = match matches.remove_one::<String>("holidays") {
+        Some(path) => TradingCalendar::load(&path)?,
+        None => TradingCalendar::default(),
+    };
+
+    let days: usize = matches.remove_one("days").unwrap_or(DEFAULT_DAYS);
+    if days == 0 {
+        return Err("--days must be at least 1".into());
+    }
+
+    let trials: usize = matches.remove_one("trials").unwrap_or(TRIALS);
+    if trials == 0 {
+        return Err("--trials must be at least 1".into());
+    }
+    // a run without --seed draws one and records it, so --from-repro repeats the run exactly
+    let seed: u64 = match matches.remove_one("seed") {
+        Some(seed) => seed,
+        None => {
+            let seed: u64 = rand::random();
+            arguments.push(format!("--seed={}", seed));
+            seed
+        }
+    };
+
+    let since: Option<NaiveDate> = parse_date_flag("--since", matches.remove_one("since"))?;
+    let until: Option<NaiveDate> = parse_date_flag("--until", matches.remove_one("until"))?;
+    if let (Some(since), Some(until)) = (since, until) {
+        if since > until {
+            return Err(format!("--since {} is after --until {}", since, until).into());
+        }
+    }
+
+    let period: Period =
+        Period::from_label(&matches.remove_one::<String>("period").unwrap()).unwrap();
+    let periods_per_year: f64 = matches
+        .remove_one("periods_per_year")
+        .unwrap_or(period.periods_per_year());
+    if !periods_per_year.is_finite() || periods_per_year <= 0.0 {
+        return Err("--periods-per-year must be a positive number".into());
+    }
+
+    let features: Vec<usize> = parse_features(&matches.remove_one::<String>("features").unwrap())?;
+
+    let chunk_size: Option<usize> = matches.remove_one("chunk_size");
+    if chunk_size == Some(0) {
+        return Err("--chunk-size must be at least 1".into());
+    }
+
+    let threads: Option<usize> = matches.remove_one("threads");
+    if threads == Some(0) {
+        return Err("--threads must be at least 1".into());
+    }
+
+    let train_frac: f32 = matches.remove_one("train_frac").unwrap();
+    if !(train_frac > 0.0 && train_frac < 1.0) {
+        return Err("--train-frac must be between 0 and 1".into());
+    }
+
+    let recency_weight: Option<f64> = matches.remove_one("recency_weight");
+    if recency_weight.is_some_and(|lambda| !(lambda > 0.0 && lambda.is_finite())) {
+        return Err("--recency-weight must be a positive number".into());
+    }
+
+    let assert_accuracy: Option<f32> = matches.remove_one("assert_accuracy");
+    if assert_accuracy.is_some_and(|pct| !(0.0..=100.0).contains(&pct)) {
+        return Err("--assert-accuracy must be a percentage from 0 to 100".into());
+    }
+
+    let meta_forests: Option<usize> = matches.remove_one("meta_forests");
+    if meta_forests == Some(0) {
+        return Err("--meta-forests must be at least 1".into());
+    }
+
+    let max_votes: usize = meta_forests
+        .or(matches.remove_one("forest_max_votes"))
+        .unwrap_or(FOREST_VOTES);
+    if max_votes == 0 {
+        return Err("--forest-max-votes must be at least 1".into());
+    }
+
+    let target_stability: Option<f64> = matches.remove_one("forest_target_stability");
+    if target_stability.is_some_and(|eps| !(eps > 0.0 && eps.is_finite())) {
+        return Err("--forest-target-stability must be a positive number".into());
+    }
+
+    let forest: ForestOptions = ForestOptions {
+        features,
+        invert: !matches.get_flag("no_invert"),
+        trees: 100,
+        max_features: None,
+        criterion: SplitCriterion::Gini,
+        train_frac,
+        recency_weight,
+        max_votes,
+        target_stability,
+    };
+
+    let grid: Vec<ForestOptions> = match matches.remove_one::<String>("grid") {
+        Some(list) => parse_grid(&list, &forest)?,
+        None => Vec::new(),
+    };
+
+    let delimiter: char = matches.remove_one("delimiter").unwrap();
+    let decimal_comma: bool = matches.get_flag("decimal_comma");
+    if decimal_comma && delimiter == ',' {
+        return Err(
+            "--decimal-comma cannot be used with a comma --delimiter, try --delimiter ';'".into(),
+        );
+    }
+
+    let prob_threshold: Option<f64> = matches.remove_one("prob_threshold");
+    if prob_threshold.is_some_and(|threshold| !(0.5..=1.0).contains(&threshold)) {
+        return Err("--prob-threshold must be between 0.5 and 1".into());
+    }
+
+    let format: OutputFormat = match matches.remove_one::<String>("format").unwrap().as_str() {
+        "table" => OutputFormat::Table,
+        "json" => OutputFormat::Json,
+        "ndjson-stats" => OutputFormat::NdjsonStats,
+        _ => OutputFormat::Text,
+    };
+
+    // the grouped tables wait for every file, which would follow the lines a streaming format already printed
+    let group_by_direction: bool = matches.get_flag("group_by_direction");
+    if group_by_direction && matches!(format, OutputFormat::Json | OutputFormat::NdjsonStats) {
+        return Err("--group-by-direction can only be used with --format text or table".into());
+    }
+
+    let detect_splits: Option<f64> = matches.remove_one("detect_splits");
+    if detect_splits.is_some_and(|threshold| threshold <= 0.0) {
+        return Err("--detect-splits threshold must be positive".into());
+    }
+
+    let stale_threshold: usize = matches.remove_one("stale_threshold").unwrap();
+    if stale_threshold < 2 {
+        return Err("--stale-threshold must be at least 2".into());
+    }
+
+    let start_price: Option<f64> = matches.remove_one("start_price");
+    if start_price.is_some_and(|price| !(price > 0.0 && price.is_finite())) {
+        return Err("--start-price must be a positive price".into());
+    }
+
+    let prob_above: Option<f64> = matches.remove_one("prob_above");
+    let prob_below: Option<f64> = matches.remove_one("prob_below");
+    if prob_above
+        .into_iter()
+        .chain(prob_below)
+        .any(|price| !(price > 0.0 && price.is_finite()))
+    {
+        return Err("--prob-above and --prob-below must be positive prices".into());
+    }
+
+    let first_passage: Option<f64> = matches.remove_one("first_passage");
+    if first_passage.is_some_and(|price| !(price > 0.0 && price.is_finite())) {
+        return Err("--first-passage must be a positive price".into());
+    }
+
+    let volume_buckets: Option<usize> = matches.remove_one("volume_buckets");
+    if volume_buckets.is_some_and(|buckets| buckets < 2) {
+        return Err("--volume-buckets must be at least 2".into());
+    }
+
+    let dup_policy: DupPolicy = match matches.remove_one::<String>("dup_policy").as_deref() {
+        Some("first") => DupPolicy::First,
+        Some("last") => DupPolicy::Last,
+        Some("average") => DupPolicy::Average,
+        Some(_) => DupPolicy::Error,
+        None if matches.get_flag("strict") => DupPolicy::Error,
+        None => DupPolicy::Last,
+    };
+
+    let return_type: ReturnType = match matches
+        .remove_one::<String>("return_type")
+        .unwrap()
+        .as_str()
+    {
+        "simple" => ReturnType::Simple,
+        _ => ReturnType::Log,
+    };
+
+    let volume_transform: VolumeTransform = match matches
+        .remove_one::<String>("volume_transform")
+        .unwrap()
+        .as_str()
+    {
+        "log" => VolumeTransform::Log,
+        "rank" => VolumeTransform::Rank,
+        _ => VolumeTransform::None,
+    };
 
-    let files_vec: Vec<String> = matches.remove_many("files").unwrap().collect();
+    let log_level: LevelFilter = if matches.get_flag("verbose") {
+        LevelFilter::Debug
+    } else if matches.get_flag("quiet") {
+        LevelFilter::Error
+    } else {
+        LevelFilter::Info
+    };
 
-    Ok(Config { files: files_vec })
+    Ok(Config {
+        files: files_vec,
+        glob_matches,
+        target_date,
+        calendar,
+        days,
+        trials,
+        seed,
+        since,
+        until,
+        period,
+        periods_per_year,
+        forest,
+        chunk_size,
+        threads,
+        horizon_sweep,
+        vol_term_structure: matches.get_flag("vol_term_structure"),
+        discount_rate: matches.remove_one("discount_rate"),
+        warmup: matches.remove_one("warmup"),
+        fan_out,
+        returns_out,
+        dump_table,
+        #[cfg(feature = "plot")]
+        plot_out,
+        debug_paths: matches.remove_one("debug_paths").unwrap(),
+        report: matches.remove_one("report"),
+        repro_out: matches.remove_one("repro_out"),
+        arguments,
+        save_model,
+        load_model,
+        predict_index: matches.remove_one("predict_index"),
+        params_only: matches.get_flag("params_only"),
+        validate: matches.get_flag("validate"),
+        self_test: matches.get_flag("self_test"),
+        list_features: matches.get_flag("list_features"),
+        print_config: matches.get_flag("print_config"),
+        help_examples: matches
+            .get_flag("help_examples")
+            .then(|| format_examples(&command)),
+        show_oob: matches.get_flag("show_oob"),
+        assert_accuracy,
+        dup_policy,
+        merge_mode,
+        risk_free_rate: matches.remove_one("rate"),
+        start_price,
+        prob_above,
+        prob_below,
+        first_passage,
+        volume_buckets,
+        volume_transform,
+        benchmark_file: matches.remove_one("benchmark_file"),
+        rolling_beta,
+        detect_splits,
+        stale_threshold,
+        adjust_splits: matches.get_flag("adjust_splits"),
+        engineered_features: matches.get_flag("engineered_features"),
+        log_level,
+        bootstrap_returns: matches.get_flag("bootstrap_returns"),
+        grid,
+        parse: ParseConfig {
+            delimiter,
+            decimal_comma,
+            strict: matches.get_flag("strict"),
+            return_type,
+        },
+        prob_threshold,
+        format,
+        group_by_direction,
+        weighted_vote: matches.get_flag("weighted_vote"),
+        explain_prediction: matches.get_flag("explain_prediction"),
+        meta_forests: meta_forests.is_some(),
+        show_provenance: matches.get_flag("provenance"),
+        metrics: matches.get_flag("metrics"),
+        money: MoneyFormat {
+            currency: matches.remove_one("currency").unwrap(),
+            thousands: matches.get_flag("thousands"),
+            precision: matches.remove_one("precision"),
+            color: use_color(
+                matches.remove_one::<String>("color").as_deref(),
+                matches.get_flag("no_color"),
+            ),
+        },
+    })
 }
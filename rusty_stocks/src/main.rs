@@ -1,17 +1,52 @@
+#[cfg(feature = "plot")]
 mod plot;
 
 use std::env;
+use std::sync::atomic::Ordering;
+
+use log::LevelFilter;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    // the first Ctrl-C lets the current step finish and the output be written, a second one quits at once
+    let handler = ctrlc::set_handler(|| {
+        if rusty_stocks::INTERRUPTED.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        eprintln!("Interrupted, finishing the current step, press Ctrl-C again to quit now");
+    });
+    if let Err(e) = handler {
+        eprintln!("Could not install the Ctrl-C handler: {}", e);
+    }
     if args.len() == 3 && args[1] == "plot" {
         // Plot the <data.csv> if the command input is "cargo run plot <path to data.csv>"
-        let file_path = &args[2];
-        if let Err(err) = plot::plot_csv(file_path) {
+        #[cfg(feature = "plot")]
+        if let Err(err) = plot::plot_csv(&args[2]) {
             eprintln!("Error: {}", err);
+            std::process::exit(1);
         }
-    } else if let Err(e) = rusty_stocks::get_args().and_then(rusty_stocks::run) {
+        #[cfg(not(feature = "plot"))]
+        {
+            eprintln!(
+                "Error: plotting needs the plot feature, build without --no-default-features"
+            );
+            std::process::exit(1);
+        }
+    } else if let Err(e) = rusty_stocks::get_args().and_then(|config| {
+        init_logger(config.get_log_level());
+        rusty_stocks::run(config)
+    }) {
         eprintln!("{}", e);
         std::process::exit(1);
     }
 }
+
+// Log to stderr at the level chosen on the command line, RUST_LOG takes precedence when it is set
+fn init_logger(level: LevelFilter) {
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_timestamp(None)
+        .format_target(false)
+        .parse_default_env()
+        .init();
+}
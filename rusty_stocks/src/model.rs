@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::calculations::TrainedForest;
+use crate::stock::Stock;
+
+// first bytes of every model file, followed by the format version
+const MAGIC: &[u8; 4] = b"RSFM";
+const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub struct SavedModel {
+    // fingerprint of the training data, see training_fingerprint
+    pub fingerprint: u64,
+    // indices of the features the forests were trained on
+    pub features: Vec<usize>,
+    pub forests: Vec<TrainedForest>,
+}
+
+/*
+    Hashes the stocks a model is trained on with 64 bit FNV-1a, so a reloaded model can tell whether it
+    was trained on the same data. Only the selected features and labels of the stocks before predict_index
+    are hashed, so appending new rows after it does not change the fingerprint
+
+    @param (stocks: &[Stock]) vector of Stock objects parsed from the input file
+    @param (features: &[usize]) indices of the features the forests are trained on
+    @param (predict_index: usize) index of the stock to predict
+
+    @return (u64) fingerprint of the training data
+*/
+pub fn training_fingerprint(stocks: &[Stock], features: &[usize], predict_index: usize) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut add = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+
+    for feature in features {
+        add(&(*feature as u64).to_le_bytes());
+    }
+    for stock in &stocks[0..predict_index] {
+        for value in stock.feature_subset(features) {
+            add(&value.to_le_bytes());
+        }
+        add(&stock.get_label().to_le_bytes());
+    }
+
+    hash
+}
+
+/*
+    Writes trained forests to a model file which load_model reads back
+
+    @param (path: &str) path of the model file, it is overwritten if it exists
+    @param (fingerprint: u64) fingerprint of the training data, see training_fingerprint
+    @param (features: &[usize]) indices of the features the forests were trained on
+    @param (forests: &[TrainedForest]) forests to save
+
+    @return (io::Result<()>) error of creating or writing the file, if any
+*/
+pub fn save_model(
+    path: &str,
+    fingerprint: u64,
+    features: &[usize],
+    forests: &[TrainedForest],
+) -> io::Result<()> {
+    let mut writer: BufWriter<File> = BufWriter::new(File::create(path)?);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&fingerprint.to_le_bytes())?;
+
+    writer.write_all(&(features.len() as u32).to_le_bytes())?;
+    for feature in features {
+        writer.write_all(&(*feature as u32).to_le_bytes())?;
+    }
+
+    writer.write_all(&(forests.len() as u32).to_le_bytes())?;
+    for forest in forests {
+        forest.serialize(&mut writer)?;
+    }
+
+    writer.flush()
+}
+
+/*
+    Reads a model file written by save_model
+
+    @param (path: &str) path of the model file
+
+    @return (io::Result<SavedModel>) the saved model, or the error of a missing or malformed file
+*/
+pub fn load_model(path: &str) -> io::Result<SavedModel> {
+    let mut reader: BufReader<File> = BufReader::new(File::open(path)?);
+
+    let mut magic: [u8; 4] = [0; 4];
+    reader.read_exact(&mut magic)?;
+    let version: u32 = read_u32(&mut reader)?;
+    if &magic != MAGIC || version != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a rusty_stocks model file of a supported version",
+        ));
+    }
+
+    let mut fingerprint: [u8; 8] = [0; 8];
+    reader.read_exact(&mut fingerprint)?;
+
+    let feature_count: u32 = read_u32(&mut reader)?;
+    let features: Vec<usize> = (0..feature_count)
+        .map(|_| read_u32(&mut reader).map(|feature| feature as usize))
+        .collect::<io::Result<_>>()?;
+
+    let forest_count: u32 = read_u32(&mut reader)?;
+    let forests: Vec<TrainedForest> = (0..forest_count)
+        .map(|_| TrainedForest::deserialize(&mut reader))
+        .collect::<io::Result<_>>()?;
+
+    Ok(SavedModel {
+        fingerprint: u64::from_le_bytes(fingerprint),
+        features,
+        forests,
+    })
+}
+
+/*
+    Reads a little endian u32 written by save_model
+
+    @param (reader: &mut R) source of the bytes
+
+    @return (io::Result<u32>) the value, or the error of the reader
+*/
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes: [u8; 4] = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
@@ -1,7 +1,7 @@
+use csv::ReaderBuilder;
 use plotters::prelude::*;
 use std::error::Error;
 use std::fs::File;
-use csv::ReaderBuilder;
 
 pub fn plot_csv(path: &str) -> Result<(), Box<dyn Error>> {
     // Read the CSV file
@@ -20,7 +20,7 @@ pub fn plot_csv(path: &str) -> Result<(), Box<dyn Error>> {
         .caption("Stock Prices", ("sans-serif", 40).into_font())
         .x_label_area_size(40)
         .y_label_area_size(40)
-        .build_cartesian_2d(0f64..250f64, 100f64..300f64)?; // Dimensions of the plot. 
+        .build_cartesian_2d(0f64..250f64, 100f64..300f64)?; // Dimensions of the plot.
 
     chart.configure_mesh().draw()?;
 
@@ -40,18 +40,22 @@ pub fn plot_csv(path: &str) -> Result<(), Box<dyn Error>> {
                     &Palette99::pick(i),
                 ))?
                 .label(header)
-                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &Palette99::pick(i)));
+                .legend(move |(x, y)| {
+                    PathElement::new(vec![(x, y), (x + 20, y)], Palette99::pick(i))
+                });
 
             chart
                 .configure_series_labels()
-                .border_style(&BLACK)
-                .background_style(&WHITE.mix(0.8))
+                .border_style(BLACK)
+                .background_style(WHITE.mix(0.8))
                 .position(SeriesLabelPosition::UpperLeft)
                 .draw()?;
-            
-            rdr = ReaderBuilder::new().has_headers(true).from_reader(File::open(path)?);
+
+            rdr = ReaderBuilder::new()
+                .has_headers(true)
+                .from_reader(File::open(path)?);
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,254 @@
+use std::borrow::Cow;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use statrs::statistics::Statistics;
+
+use crate::analysis::Direction;
+use crate::calculations::{
+    calculate_final_prices, calculate_price_paths, engineer_features, final_prices, tally_votes,
+    train_votes, ForestOptions, ForestVote, SimulationOptions, TrainedForest,
+};
+use crate::model::{load_model, training_fingerprint, SavedModel};
+use crate::stock::Stock;
+use crate::{CustomResult, MIN_ROWS};
+
+// settings of a prediction made by predict
+#[derive(Debug, Clone)]
+pub struct PredictionConfig {
+    pub simulation: SimulationOptions,
+    pub forest: ForestOptions,
+    // share of the trees the forests need for a direction, None for a plain majority vote
+    pub prob_threshold: Option<f64>,
+    // weight the vote of each forest by its test accuracy
+    pub weighted_vote: bool,
+    // seed of the random number generator, None for a different prediction every call
+    pub seed: Option<u64>,
+    // simulate the random walks this many at a time, keeping only their final prices, see calculate_final_prices
+    pub chunk_size: Option<usize>,
+    // average the simulated prices of every day from this one on instead of only the last day
+    pub warmup: Option<usize>,
+    // row whose next day the forests predict, None for the last row
+    pub predict_index: Option<usize>,
+    // train on the rows of engineer_features, forest.features must then list the engineered indices too
+    pub engineered_features: bool,
+    // path of a model saved by save_model whose forests vote instead of newly trained ones
+    pub load_model: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PredictionResult {
+    // mean of the simulated prices
+    #[serde(rename = "predicted_price")]
+    pub mean_price: f64,
+    // "increase", "decrease" or "uncertain" in JSON
+    pub direction: Direction,
+    // average test accuracy of the voting forests
+    pub accuracy: f32,
+}
+
+// what predict_with_details works out on the way to the prediction, for callers which report more than it
+#[derive(Debug)]
+pub struct PredictionDetails<'a> {
+    // every simulated path, None when the simulation ran in chunks
+    pub price_paths: Option<Vec<Vec<f64>>>,
+    // prices the predicted price is the mean of
+    pub simulated_prices: Vec<f64>,
+    // stocks the forests voted on, engineered if asked for, and the index of the predicted one among them
+    pub forest_stocks: Cow<'a, [Stock]>,
+    pub forest_index: usize,
+    // fingerprint of the training data, see training_fingerprint
+    pub fingerprint: u64,
+    // the loaded model was trained on data with another fingerprint
+    pub stale_model: bool,
+    pub forests: Vec<TrainedForest>,
+    pub vote: ForestVote,
+}
+
+// one object of --format json: {"schema_version", "file", "provenance", "predicted_price", "direction", "accuracy"}
+#[derive(Debug, Serialize)]
+pub struct FilePrediction<'a> {
+    pub schema_version: u32,
+    pub file: &'a str,
+    // SHA-256 of the parsed rows and the settings, see --provenance
+    pub provenance: &'a str,
+    #[serde(flatten)]
+    pub prediction: &'a PredictionResult,
+}
+
+impl PredictionResult {
+    /*
+        Summarizes the simulated prices and the forest vote into a prediction
+
+        @param (simulated_prices: &[f64]) simulated prices of the Monte Carlo forecast
+        @param (vote: &ForestVote) vote of the forests on the direction
+        @param (prob_threshold: Option<f64>) share of the trees needed for a direction, None for a majority vote
+        @param (weighted_vote: bool) whether the majority is weighted by the test accuracy of the forests
+
+        @return (PredictionResult) mean simulated price, direction and test accuracy
+    */
+    pub fn new(
+        simulated_prices: &[f64],
+        vote: &ForestVote,
+        prob_threshold: Option<f64>,
+        weighted_vote: bool,
+    ) -> PredictionResult {
+        let direction: Direction = match prob_threshold {
+            Some(threshold) if vote.probability >= threshold => Direction::Increase,
+            Some(threshold) if vote.probability <= 1.0 - threshold => Direction::Decrease,
+            Some(_) => Direction::Uncertain,
+            None if weighted_vote && vote.is_weighted_increase() => Direction::Increase,
+            None if !weighted_vote && vote.is_increase() => Direction::Increase,
+            None => Direction::Decrease,
+        };
+
+        PredictionResult {
+            mean_price: simulated_prices.iter().mean(),
+            direction,
+            accuracy: vote.accuracy,
+        }
+    }
+}
+
+/*
+    Predicts the price of the stock at the end of the simulation with Monte Carlo random walks and the
+    direction of the day after the last stock with a random forest vote, without printing anything
+
+    @param (stocks: &[Stock]) labeled stocks in date order, such as those returned by parse_stocks
+    @param (config: &PredictionConfig) settings of the simulation, the forests and the vote
+
+    @return (CustomResult<PredictionResult>) CustomResult containing the prediction, or the error of too few
+    stocks, of estimating the return distribution, of loading the model or of building a training table
+*/
+pub fn predict(stocks: &[Stock], config: &PredictionConfig) -> CustomResult<PredictionResult> {
+    let (prediction, _) = predict_with_details(stocks, config)?;
+
+    Ok(prediction)
+}
+
+/*
+    Makes the prediction of predict and keeps the price paths, the forests and their vote, so a caller can
+    report more than the prediction without repeating the simulation or the training
+
+    @param (stocks: &[Stock]) labeled stocks in date order, such as those returned by parse_stocks
+    @param (config: &PredictionConfig) settings of the simulation, the forests and the vote
+
+    @return (CustomResult<(PredictionResult, PredictionDetails)>) CustomResult containing the prediction and
+    how it was made, or the error of too few stocks, of estimating the return distribution, of loading the
+    model or of building a training table
+*/
+pub fn predict_with_details<'a>(
+    stocks: &'a [Stock],
+    config: &PredictionConfig,
+) -> CustomResult<(PredictionResult, PredictionDetails<'a>)> {
+    if stocks.len() < MIN_ROWS {
+        return Err(format!(
+            "has {} rows, too few to predict (need at least {})",
+            stocks.len(),
+            MIN_ROWS
+        )
+        .into());
+    }
+    let predict_index: usize = config.predict_index.unwrap_or(stocks.len() - 1);
+    if predict_index >= stocks.len() || predict_index < 2 {
+        return Err(format!(
+            "row {} cannot be predicted, it needs two earlier rows to train on and the file has {} rows",
+            predict_index,
+            stocks.len()
+        )
+        .into());
+    }
+
+    let mut rng: StdRng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let days: usize = config.simulation.days;
+    let (price_paths, simulated_prices): (Option<Vec<Vec<f64>>>, Vec<f64>) = match config.chunk_size
+    {
+        Some(chunk_size) => (
+            None,
+            calculate_final_prices(stocks, &config.simulation, chunk_size, &mut rng)?,
+        ),
+        None => {
+            let price_paths: Vec<Vec<f64>> =
+                calculate_price_paths(stocks, &config.simulation, &mut rng)?;
+            let simulated_prices: Vec<f64> = match config.warmup {
+                Some(warmup) if warmup >= days => {
+                    return Err(format!(
+                        "the warmup day {} is past the last simulated day {}",
+                        warmup,
+                        days - 1
+                    )
+                    .into())
+                }
+                // every day of every path from the warmup day on, day 0 is the current price
+                Some(warmup) => price_paths[warmup..].concat(),
+                None => final_prices(&price_paths).to_vec(),
+            };
+            (Some(price_paths), simulated_prices)
+        }
+    };
+
+    let (forest_stocks, forest_index): (Cow<[Stock]>, usize) = if config.engineered_features {
+        let engineered: Vec<Stock> = engineer_features(stocks)
+            .map_err(|e| format!("could not parse a date for the day of the week: {}", e))?;
+
+        // the first rows lack the history the indicators need and are skipped
+        let skipped: usize = stocks.len() - engineered.len();
+        if predict_index < skipped + 2 {
+            return Err(format!(
+                "the engineered features skip the first {} rows, leaving too few rows before row {} to train on",
+                skipped, predict_index
+            )
+            .into());
+        }
+        (Cow::Owned(engineered), predict_index - skipped)
+    } else {
+        (Cow::Borrowed(stocks), predict_index)
+    };
+    let features: &[usize] = &config.forest.features;
+
+    let fingerprint: u64 = training_fingerprint(&forest_stocks, features, forest_index);
+    let (forests, stale_model): (Vec<TrainedForest>, bool) = match &config.load_model {
+        Some(path) => {
+            let model: SavedModel = load_model(path)
+                .map_err(|e| format!("could not load the model {}: {}", path, e))?;
+            if model.features != features {
+                return Err(format!("the model in {} was trained on other features", path).into());
+            }
+            if model.forests.is_empty() {
+                return Err(format!("the model in {} has no forests", path).into());
+            }
+            (model.forests, model.fingerprint != fingerprint)
+        }
+        None => (
+            train_votes(&forest_stocks, &config.forest, forest_index, &mut rng)?,
+            false,
+        ),
+    };
+
+    let vote: ForestVote = tally_votes(&forests, &forest_stocks[forest_index], features);
+    let prediction: PredictionResult = PredictionResult::new(
+        &simulated_prices,
+        &vote,
+        config.prob_threshold,
+        config.weighted_vote,
+    );
+
+    Ok((
+        prediction,
+        PredictionDetails {
+            price_paths,
+            simulated_prices,
+            forest_stocks,
+            forest_index,
+            fingerprint,
+            stale_model,
+            forests,
+            vote,
+        },
+    ))
+}
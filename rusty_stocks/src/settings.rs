@@ -0,0 +1,226 @@
+use std::fmt::Display;
+use std::fs;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::CustomResult;
+
+// analysis parameters read from a --config TOML file, every key is the long flag with underscores
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub period: Option<String>,
+    pub periods_per_year: Option<f64>,
+    pub target_date: Option<String>,
+    pub holidays: Option<String>,
+    pub days: Option<usize>,
+    pub trials: Option<usize>,
+    pub seed: Option<u64>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub features: Option<String>,
+    pub train_frac: Option<f32>,
+    pub recency_weight: Option<f64>,
+    pub forest_max_votes: Option<usize>,
+    pub meta_forests: Option<usize>,
+    pub forest_target_stability: Option<f64>,
+    pub no_invert: Option<bool>,
+    pub prob_threshold: Option<f64>,
+    pub weighted_vote: Option<bool>,
+    pub engineered_features: Option<bool>,
+    pub volume_buckets: Option<usize>,
+    pub volume_transform: Option<String>,
+    pub chunk_size: Option<usize>,
+    pub threads: Option<usize>,
+    pub risk_neutral: Option<bool>,
+    pub rate: Option<f64>,
+    pub bootstrap_returns: Option<bool>,
+    pub stale_threshold: Option<usize>,
+    pub detect_splits: Option<f64>,
+    pub adjust_splits: Option<bool>,
+    pub delimiter: Option<char>,
+    pub decimal_comma: Option<bool>,
+    pub strict: Option<bool>,
+    pub dup_policy: Option<String>,
+    pub merge_mode: Option<String>,
+    pub return_type: Option<String>,
+    pub format: Option<String>,
+    pub currency: Option<String>,
+    pub thousands: Option<bool>,
+    pub precision: Option<usize>,
+    pub color: Option<String>,
+    pub metrics: Option<bool>,
+    pub show_oob: Option<bool>,
+    pub assert_accuracy: Option<f32>,
+    pub provenance: Option<bool>,
+    pub report: Option<String>,
+    pub verbose: Option<bool>,
+    pub quiet: Option<bool>,
+}
+
+impl Settings {
+    /*
+        Reads the settings from a TOML file, unknown keys are rejected so a misspelled setting is not ignored
+
+        @param (path: &str) path of the TOML file
+
+        @return (CustomResult<Settings>) CustomResult containing the settings, or the error of reading or
+        parsing the file
+    */
+    pub fn load(path: &str) -> CustomResult<Settings> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|e| format!("could not read the config {}: {}", path, e))?;
+
+        toml::from_str(&text).map_err(|e| format!("invalid config {}: {}", path, e).into())
+    }
+
+    /*
+        Converts the settings into the command line flags they stand for, a false flag is left out
+
+        @param (&self) current settings
+
+        @return (Vec<String>) flags such as "--period=1wk" or "--strict"
+    */
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = Vec::new();
+
+        push_value(&mut args, "period", &self.period);
+        push_value(&mut args, "periods-per-year", &self.periods_per_year);
+        push_value(&mut args, "target-date", &self.target_date);
+        push_value(&mut args, "holidays", &self.holidays);
+        push_value(&mut args, "days", &self.days);
+        push_value(&mut args, "trials", &self.trials);
+        push_value(&mut args, "seed", &self.seed);
+        push_value(&mut args, "since", &self.since);
+        push_value(&mut args, "until", &self.until);
+        push_value(&mut args, "features", &self.features);
+        push_value(&mut args, "train-frac", &self.train_frac);
+        push_value(&mut args, "recency-weight", &self.recency_weight);
+        push_value(&mut args, "forest-max-votes", &self.forest_max_votes);
+        push_value(&mut args, "meta-forests", &self.meta_forests);
+        push_value(
+            &mut args,
+            "forest-target-stability",
+            &self.forest_target_stability,
+        );
+        push_flag(&mut args, "no-invert", self.no_invert);
+        push_value(&mut args, "prob-threshold", &self.prob_threshold);
+        push_flag(&mut args, "weighted-vote", self.weighted_vote);
+        push_flag(&mut args, "engineered-features", self.engineered_features);
+        push_value(&mut args, "volume-buckets", &self.volume_buckets);
+        push_value(&mut args, "volume-transform", &self.volume_transform);
+        push_value(&mut args, "chunk-size", &self.chunk_size);
+        push_value(&mut args, "threads", &self.threads);
+        push_flag(&mut args, "risk-neutral", self.risk_neutral);
+        push_value(&mut args, "rate", &self.rate);
+        push_flag(&mut args, "bootstrap-returns", self.bootstrap_returns);
+        push_value(&mut args, "stale-threshold", &self.stale_threshold);
+        push_value(&mut args, "detect-splits", &self.detect_splits);
+        push_flag(&mut args, "adjust-splits", self.adjust_splits);
+        push_value(&mut args, "delimiter", &self.delimiter);
+        push_flag(&mut args, "decimal-comma", self.decimal_comma);
+        push_flag(&mut args, "strict", self.strict);
+        push_value(&mut args, "dup-policy", &self.dup_policy);
+        push_value(&mut args, "merge-mode", &self.merge_mode);
+        push_value(&mut args, "return-type", &self.return_type);
+        push_value(&mut args, "format", &self.format);
+        push_value(&mut args, "currency", &self.currency);
+        push_flag(&mut args, "thousands", self.thousands);
+        push_value(&mut args, "precision", &self.precision);
+        push_value(&mut args, "color", &self.color);
+        push_flag(&mut args, "metrics", self.metrics);
+        push_flag(&mut args, "show-oob", self.show_oob);
+        push_value(&mut args, "assert-accuracy", &self.assert_accuracy);
+        push_flag(&mut args, "provenance", self.provenance);
+        push_value(&mut args, "report", &self.report);
+        push_flag(&mut args, "verbose", self.verbose);
+        push_flag(&mut args, "quiet", self.quiet);
+
+        args
+    }
+}
+
+/*
+    Finds the value given to a flag on the command line, before the rest of the arguments are parsed
+
+    @param (args: &[String]) command line arguments, starting with the program name
+    @param (flag: &str) long flag such as "--config"
+
+    @return (Option<String>) value of the flag, if it is given
+*/
+pub fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    let prefix: String = format!("{}=", flag);
+
+    args.iter().enumerate().skip(1).find_map(|(idx, arg)| {
+        if arg == flag {
+            args.get(idx + 1).cloned()
+        } else {
+            arg.strip_prefix(&prefix).map(String::from)
+        }
+    })
+}
+
+/*
+    Removes a flag and its value from command line arguments
+
+    @param (args: &[String]) command line arguments
+    @param (flag: &str) long flag such as "--config"
+
+    @return (Vec<String>) the arguments without the flag
+*/
+pub fn without_flag(args: &[String], flag: &str) -> Vec<String> {
+    let prefix: String = format!("{}=", flag);
+
+    let mut kept: Vec<String> = Vec::new();
+    let mut skip_value: bool = false;
+    for arg in args {
+        if skip_value {
+            skip_value = false;
+        } else if arg == flag {
+            skip_value = true;
+        } else if !arg.starts_with(&prefix) {
+            kept.push(arg.clone());
+        }
+    }
+
+    kept
+}
+
+/*
+    Reads the arguments recorded in a manifest written with --repro-out
+
+    @param (path: &str) path of the JSON manifest
+
+    @return (CustomResult<Vec<String>>) CustomResult containing the recorded arguments, without the program name
+*/
+pub fn load_repro_arguments(path: &str) -> CustomResult<Vec<String>> {
+    let text: String = fs::read_to_string(path)
+        .map_err(|e| format!("could not read the manifest {}: {}", path, e))?;
+    let manifest: Value =
+        serde_json::from_str(&text).map_err(|e| format!("invalid manifest {}: {}", path, e))?;
+
+    manifest["arguments"]
+        .as_array()
+        .and_then(|arguments| {
+            arguments
+                .iter()
+                .map(|argument| argument.as_str().map(String::from))
+                .collect()
+        })
+        .ok_or_else(|| format!("the manifest {} has no list of arguments", path).into())
+}
+
+// Appends --flag=value when the setting is present
+fn push_value<T: Display>(args: &mut Vec<String>, flag: &str, value: &Option<T>) {
+    if let Some(value) = value {
+        args.push(format!("--{}={}", flag, value));
+    }
+}
+
+// Appends --flag when the setting is true
+fn push_flag(args: &mut Vec<String>, flag: &str, value: Option<bool>) {
+    if value == Some(true) {
+        args.push(format!("--{}", flag));
+    }
+}
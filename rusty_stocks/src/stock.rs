@@ -1,5 +1,164 @@
 use core::fmt;
 
+use chrono::{NaiveDate, NaiveDateTime, ParseResult};
+
+// names of the random forest features in the order returned by get_array
+pub const FEATURE_NAMES: [&str; 6] = ["open", "high", "low", "adj_close", "close", "volume"];
+
+// names of the features set by calculations::engineer_features, indexed after FEATURE_NAMES
+pub const ENGINEERED_FEATURE_NAMES: [&str; 3] = ["sma_ratio", "rsi", "day_of_week"];
+
+/*
+    Parses a date in the YYYY-MM-DD format used by Yahoo Finance exports or, for intraday bars, a
+    YYYY-MM-DD HH:MM[:SS] datetime with a space or a T before the time
+
+    @param (date: &str) date field of a data row
+
+    @return (ParseResult<NaiveDate>) the day of the date
+*/
+pub fn parse_date(date: &str) -> ParseResult<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").or_else(|e| {
+        [
+            "%Y-%m-%d %H:%M:%S",
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y-%m-%d %H:%M",
+            "%Y-%m-%dT%H:%M",
+        ]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .map(|datetime| datetime.date())
+        .ok_or(e)
+    })
+}
+
+/*
+    Looks up the name of a random forest feature, engineered features follow the raw ones
+
+    @param (idx: usize) index into FEATURE_NAMES followed by ENGINEERED_FEATURE_NAMES
+
+    @return (&str) name of the feature
+*/
+pub fn feature_name(idx: usize) -> &'static str {
+    FEATURE_NAMES
+        .iter()
+        .chain(ENGINEERED_FEATURE_NAMES.iter())
+        .nth(idx)
+        .unwrap()
+}
+
+/*
+    Looks up the index of a random forest feature by its name, the inverse of feature_name
+
+    @param (name: &str) name of a raw or engineered feature
+
+    @return (Option<usize>) index into FEATURE_NAMES followed by ENGINEERED_FEATURE_NAMES, None if unknown
+*/
+pub fn feature_index(name: &str) -> Option<usize> {
+    FEATURE_NAMES
+        .iter()
+        .chain(ENGINEERED_FEATURE_NAMES.iter())
+        .position(|feature| *feature == name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReturnType {
+    // ln(price today / price yesterday), which the geometric Brownian motion of the simulation assumes
+    Log,
+    // price today / price yesterday - 1
+    Simple,
+}
+
+impl ReturnType {
+    /*
+        Calculates the return from one price to the next
+
+        @param (&self) current return type
+        @param (price_yesterday: f64) earlier price
+        @param (price_today: f64) later price
+
+        @return (f64) log or simple return
+    */
+    pub fn calculate(&self, price_yesterday: f64, price_today: f64) -> f64 {
+        match self {
+            ReturnType::Log => (price_today / price_yesterday).ln(),
+            ReturnType::Simple => price_today / price_yesterday - 1.0,
+        }
+    }
+
+    /*
+        Returns the name of the return type as used on the command line
+
+        @param (&self) current return type
+
+        @return (&str) "log" or "simple"
+    */
+    pub fn get_name(&self) -> &str {
+        match self {
+            ReturnType::Log => "log",
+            ReturnType::Simple => "simple",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    /*
+        Parses the label of a bar length as used by Yahoo Finance
+
+        @param (label: &str) "1h", "1d", "1wk" or "1mo"
+
+        @return (Option<Period>) matching Period, or None for any other label
+    */
+    pub fn from_label(label: &str) -> Option<Period> {
+        match label {
+            "1h" => Some(Period::Hour),
+            "1d" => Some(Period::Day),
+            "1wk" => Some(Period::Week),
+            "1mo" => Some(Period::Month),
+            _ => None,
+        }
+    }
+
+    /*
+        Returns the label of the period, the inverse of from_label
+
+        @param (&self) current period
+
+        @return (&str) "1h", "1d", "1wk" or "1mo"
+    */
+    pub fn get_label(&self) -> &str {
+        match self {
+            Period::Hour => "1h",
+            Period::Day => "1d",
+            Period::Week => "1wk",
+            Period::Month => "1mo",
+        }
+    }
+
+    /*
+        Returns the number of bars of this length in a trading year, 252 trading days of 6.5 hours
+
+        @param (&self) current period
+
+        @return (f64) default number of periods per year
+    */
+    pub fn periods_per_year(&self) -> f64 {
+        match self {
+            Period::Hour => 1638.0,
+            Period::Day => 252.0,
+            Period::Week => 52.0,
+            Period::Month => 12.0,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Tomorrow {
     Increase,
@@ -7,6 +166,129 @@ pub enum Tomorrow {
     Predict,
 }
 
+impl Tomorrow {
+    /*
+        Inverts get_label, turning a random forest label back into a Tomorrow
+
+        @param (label: f64) 1 for increasing, 0 for decreasing, -1 for a stock still to be predicted
+
+        @return (Option<Tomorrow>) matching Tomorrow, or None if the label is not one of the three values
+    */
+    pub fn from_label(label: f64) -> Option<Tomorrow> {
+        if label == 1.0 {
+            Some(Tomorrow::Increase)
+        } else if label == 0.0 {
+            Some(Tomorrow::Decrease)
+        } else if label == -1.0 {
+            Some(Tomorrow::Predict)
+        } else {
+            None
+        }
+    }
+
+    /*
+        Returns the random forest label of the current tomorrow object
+
+        @param (&self) current tomorrow object
+
+        @return (f64) 1 for increasing, 0 for decreasing, -1 in the case this should not be read
+    */
+    pub fn get_label(&self) -> f64 {
+        match self {
+            Tomorrow::Increase => 1.0,
+            Tomorrow::Decrease => 0.0,
+            Tomorrow::Predict => -1.0,
+        }
+    }
+
+    /*
+        Checks if the price increases (or stays the same) tomorrow
+
+        @param (&self) current tomorrow object
+
+        @return (bool) true for Tomorrow::Increase
+    */
+    pub fn is_increase(&self) -> bool {
+        matches!(self, Tomorrow::Increase)
+    }
+
+    /*
+        Checks if the price decreases tomorrow
+
+        @param (&self) current tomorrow object
+
+        @return (bool) true for Tomorrow::Decrease
+    */
+    pub fn is_decrease(&self) -> bool {
+        matches!(self, Tomorrow::Decrease)
+    }
+
+    /*
+        Checks if tomorrow is not known yet and has to be predicted
+
+        @param (&self) current tomorrow object
+
+        @return (bool) true for Tomorrow::Predict
+    */
+    pub fn is_predict(&self) -> bool {
+        matches!(self, Tomorrow::Predict)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StockError {
+    // the high is below the low
+    HighBelowLow {
+        date: String,
+        high: f64,
+        low: f64,
+    },
+    // the open or close is outside of the low to high range
+    OutsideRange {
+        date: String,
+        field: &'static str,
+        value: f64,
+        low: f64,
+        high: f64,
+    },
+    // a price is zero, negative or not a number
+    InvalidPrice {
+        date: String,
+        field: &'static str,
+        value: f64,
+    },
+}
+
+impl fmt::Display for StockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StockError::HighBelowLow { date, high, low } => {
+                write!(f, "{}: the high {} is below the low {}", date, high, low)
+            }
+            StockError::OutsideRange {
+                date,
+                field,
+                value,
+                low,
+                high,
+            } => write!(
+                f,
+                "{}: the {} {} is outside of the low to high range {} to {}",
+                date, field, value, low, high
+            ),
+            StockError::InvalidPrice { date, field, value } => {
+                write!(
+                    f,
+                    "{}: the {} {} is not a positive price",
+                    date, field, value
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for StockError {}
+
 #[derive(Debug, Clone)]
 pub struct Stock {
     date: String,
@@ -15,9 +297,13 @@ pub struct Stock {
     low: f64,
     close: f64,
     adj_close: f64,
-    volume: usize,
+    volume: u64,
     tomorrow: Tomorrow,
-    log_return: f64,
+    // log or simple return from the previous stock, see ReturnType
+    period_return: f64,
+    // bucket or transform of the volume which replaces it as a random forest feature
+    volume_feature: Option<f64>,
+    engineered: Option<[f64; 3]>,
 }
 
 impl fmt::Display for Stock {
@@ -39,7 +325,7 @@ impl Stock {
         low: f64,
         close: f64,
         adj_close: f64,
-        volume: usize,
+        volume: u64,
         tomorrow: Tomorrow,
     ) -> Self {
         Self {
@@ -51,7 +337,9 @@ impl Stock {
             adj_close,
             volume,
             tomorrow,
-            log_return: 0.0,
+            period_return: 0.0,
+            volume_feature: None,
+            engineered: None,
         }
     }
 
@@ -60,7 +348,8 @@ impl Stock {
 
         @param (&self) current stock object
 
-        @return ([f64; 6]) array of length six holding the open, high, low, adj_close, close and volume data
+        @return ([f64; 6]) array of length six holding the open, high, low, adj_close, close and volume data,
+        with the volume bucket or transform in place of the volume once one is set
     */
     pub fn get_array(&self) -> [f64; 6] {
         [
@@ -69,10 +358,82 @@ impl Stock {
             self.low,
             self.adj_close,
             self.close,
-            self.volume as f64,
+            self.volume_feature.unwrap_or(self.volume as f64),
         ]
     }
 
+    /*
+        Checks the invariants of a daily bar: every price is positive, the high is not below the low and the
+        open and close are within the low to high range. The volume is unsigned, so it cannot be negative
+
+        @param (&self) current stock object
+
+        @return (Result<(), StockError>) the first violated invariant, naming the date of the stock
+    */
+    pub fn validate(&self) -> Result<(), StockError> {
+        let prices: [(&'static str, f64); 5] = [
+            ("open", self.open),
+            ("high", self.high),
+            ("low", self.low),
+            ("close", self.close),
+            ("adj_close", self.adj_close),
+        ];
+        for (field, value) in prices {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(StockError::InvalidPrice {
+                    date: self.date.clone(),
+                    field,
+                    value,
+                });
+            }
+        }
+
+        if self.high < self.low {
+            return Err(StockError::HighBelowLow {
+                date: self.date.clone(),
+                high: self.high,
+                low: self.low,
+            });
+        }
+
+        for (field, value) in [("open", self.open), ("close", self.close)] {
+            if value < self.low || value > self.high {
+                return Err(StockError::OutsideRange {
+                    date: self.date.clone(),
+                    field,
+                    value,
+                    low: self.low,
+                    high: self.high,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /*
+        Returns the selected subset of the random forest features, in the order they were requested
+
+        @param (&self) current stock object
+        @param (features: &[usize]) indices into FEATURE_NAMES, followed by ENGINEERED_FEATURE_NAMES, of the
+        features to keep
+
+        @return (Vec<f64>) vector holding only the selected features, engineered features are NaN until set
+    */
+    pub fn feature_subset(&self, features: &[usize]) -> Vec<f64> {
+        let array: [f64; 6] = self.get_array();
+
+        features
+            .iter()
+            .map(|idx| match idx.checked_sub(FEATURE_NAMES.len()) {
+                None => array[*idx],
+                Some(engineered_idx) => self
+                    .engineered
+                    .map_or(f64::NAN, |engineered| engineered[engineered_idx]),
+            })
+            .collect()
+    }
+
     /*
         Returns the label (increasing or decreasing) of the current stock object
 
@@ -81,11 +442,30 @@ impl Stock {
         @return (f64) 1 for increasing, 0 for decreasing, -1 in the case this should not be read
     */
     pub fn get_label(&self) -> f64 {
-        match self.tomorrow {
-            Tomorrow::Increase => 1.0,
-            Tomorrow::Decrease => 0.0,
-            Tomorrow::Predict => -1.0,
-        }
+        self.tomorrow.get_label()
+    }
+
+    /*
+        Parses the date attribute, which is expected in the YYYY-MM-DD format used by Yahoo Finance exports
+        or, for intraday bars, as a YYYY-MM-DD HH:MM[:SS] datetime with a space or a T before the time
+
+        @param (&self) current stock object
+
+        @return (ParseResult<NaiveDate>) typed date of the current stock object
+    */
+    pub fn get_date(&self) -> ParseResult<NaiveDate> {
+        parse_date(&self.date)
+    }
+
+    /*
+        Simple getter for the date attribute as it appeared in the input file
+
+        @param (&self) current stock object
+
+        @return (String) date attribute
+    */
+    pub fn get_date_string(&self) -> String {
+        self.date.clone()
     }
 
     /*
@@ -110,6 +490,17 @@ impl Stock {
         self.close
     }
 
+    /*
+        Simple getter for adj_close attribute used to detect splits and dividends
+
+        @param (&self) current stock object
+
+        @return (f64) adj_close attribute
+    */
+    pub fn get_adj_close(&self) -> f64 {
+        self.adj_close
+    }
+
     /*
         Simple getter to get average price of the stock
 
@@ -122,14 +513,14 @@ impl Stock {
     }
 
     /*
-        Simple getter to get the logarithmic return
+        Simple getter to get the return, calculations::calculate_returns gives the whole series
 
         @param (&self) current stock object
 
-        @return (f64) logarithmic return of current stock object
+        @return (f64) log or simple return of current stock object
     */
     pub fn get_return(&self) -> f64 {
-        self.log_return
+        self.period_return
     }
 
     /*
@@ -142,12 +533,100 @@ impl Stock {
     }
 
     /*
-        Setter function for the logarithmic return on the current stock
+        Setter for the bucket or transform of the volume which replaces the raw volume as a random forest feature
+
+        @param (&mut self) current stock object
+        @param (value: f64) bucket of the volume among recent volumes, or the transformed volume
+    */
+    pub fn set_volume_feature(&mut self, value: f64) {
+        self.volume_feature = Some(value);
+    }
+
+    /*
+        Getter for the raw traded volume, before any bucket or transform
+
+        @param (&self) current stock object
+
+        @return (u64) number of shares traded
+    */
+    pub fn get_volume(&self) -> u64 {
+        self.volume
+    }
+
+    /*
+        Setter for the engineered features used alongside the raw data by the random forest
+
+        @param (&mut self) current stock object
+        @param (engineered: [f64; 3]) short/long SMA ratio, RSI and day of the week
+    */
+    pub fn set_engineered_features(&mut self, engineered: [f64; 3]) {
+        self.engineered = Some(engineered);
+    }
+
+    /*
+        Scales the open, high, low and close by adj_close / close, so prices before a split or dividend
+        line up with the ones after it the way adj_close already does
+
+        @param (&mut self) current stock object
+    */
+    pub fn adjust_prices(&mut self) {
+        let factor: f64 = self.adj_close / self.close;
+
+        self.open *= factor;
+        self.high *= factor;
+        self.low *= factor;
+        self.close = self.adj_close;
+    }
+
+    /*
+        Multiplies the prices by a ratio and divides the volume by it, the way a split changes both, so a
+        series can be joined to another one at a different level
+
+        @param (&mut self) current stock object
+        @param (ratio: f64) factor the prices are multiplied by
+    */
+    pub fn scale(&mut self, ratio: f64) {
+        self.open *= ratio;
+        self.high *= ratio;
+        self.low *= ratio;
+        self.close *= ratio;
+        self.adj_close *= ratio;
+        self.volume = (self.volume as f64 / ratio).round() as u64;
+    }
+
+    /*
+        Merges rows of the same date, such as a row and its correction, into one row with the average of
+        their prices and the sum of their volumes
+
+        @param (rows: &[Stock]) stock objects sharing a date, at least one
+
+        @return (Stock) stock object with the date and label of the last row
+    */
+    pub fn average(rows: &[Stock]) -> Stock {
+        let count: f64 = rows.len() as f64;
+        let mean = |price: fn(&Stock) -> f64| rows.iter().map(price).sum::<f64>() / count;
+        let last: &Stock = &rows[rows.len() - 1];
+
+        Stock::new(
+            last.date.clone(),
+            mean(|stock| stock.open),
+            mean(|stock| stock.high),
+            mean(|stock| stock.low),
+            mean(|stock| stock.close),
+            mean(|stock| stock.adj_close),
+            rows.iter().map(|stock| stock.volume).sum(),
+            last.tomorrow.clone(),
+        )
+    }
+
+    /*
+        Setter function for the return on the current stock
 
         @param (&mut self) current stock object
         @param (price_yesterday: f64) price of stock from the day before
+        @param (return_type: ReturnType) whether the return is logarithmic or simple
     */
-    pub fn set_return(&mut self, price_yesterday: f64) {
-        self.log_return = (self.get_price() / price_yesterday).ln();
+    pub fn set_return(&mut self, price_yesterday: f64, return_type: ReturnType) {
+        self.period_return = return_type.calculate(price_yesterday, self.get_price());
     }
 }
@@ -0,0 +1,154 @@
+use chrono::NaiveDate;
+
+use rusty_stocks::analysis::{
+    format_grouped, format_table, AnalysisResult, Direction, MoneyFormat, Outlook,
+};
+use rusty_stocks::stock::Period;
+
+fn result(filename: &str, predicted_price: f64, direction: Direction) -> AnalysisResult {
+    AnalysisResult {
+        filename: filename.to_string(),
+        rows: 10,
+        first_date: String::from("2024-01-01"),
+        last_date: String::from("2024-01-10"),
+        current_price: 100.0,
+        days: 30,
+        target_date: None,
+        trials: 100,
+        predicted_price,
+        risk_neutral: false,
+        confidence_interval: (90.0, 110.0),
+        outlook_band: (95.0, 105.0),
+        outlook: Outlook::Flat,
+        features: vec![0, 1, 2, 3, 4, 5],
+        split_dates: Vec::new(),
+        provenance: String::new(),
+        period: Period::Day,
+        direction,
+        prob_threshold: None,
+        weighted_increase: None,
+        probability: 0.5,
+        accuracy: 0.5,
+        confusion: [[1, 1], [1, 1]],
+    }
+}
+
+#[test]
+fn grouped_output_lists_increases_first_by_change() {
+    let results = vec![
+        result("small_up.csv", 101.0, Direction::Increase),
+        result("down.csv", 95.0, Direction::Decrease),
+        result("big_up.csv", 90.0, Direction::Increase),
+    ];
+    let money = MoneyFormat {
+        currency: String::new(),
+        thousands: false,
+        precision: None,
+        color: false,
+    };
+
+    let grouped = format_grouped(&results, &money);
+    let position = |name: &str| grouped.find(name).unwrap();
+
+    assert!(position("Predicted increase (2)") < position("big_up.csv"));
+    assert!(position("big_up.csv") < position("small_up.csv"));
+    assert!(position("small_up.csv") < position("Predicted decrease (1)"));
+    assert!(position("Predicted decrease (1)") < position("down.csv"));
+    assert!(!grouped.contains("Predicted uncertain"));
+}
+
+#[test]
+fn outlook_is_flat_inside_the_band() {
+    assert_eq!(Outlook::classify(100.0, (95.0, 105.0)), Outlook::Flat);
+    assert_eq!(Outlook::classify(90.0, (95.0, 105.0)), Outlook::Up);
+    assert_eq!(Outlook::classify(110.0, (95.0, 105.0)), Outlook::Down);
+}
+
+#[test]
+fn display_matches_the_text_output() {
+    let mut up = result("up.csv", 110.0, Direction::Increase);
+    up.accuracy = 0.75;
+
+    assert_eq!(
+        up.to_string(),
+        "Monte Carlo methods predict a price of 110.00 after 29 days!\n\
+         The forecast is flat, the current price is inside the 50% band of 95.00 to 105.00\n\
+         The Random Forest predicts an increase with a test accuracy of 75%!\n"
+    );
+}
+
+#[test]
+fn json_round_trip_keeps_every_field() {
+    let mut original = result("round_trip.csv", 104.123456789, Direction::Uncertain);
+    original.target_date = NaiveDate::from_ymd_opt(2024, 3, 1);
+    original.features = vec![0, 5, 7];
+    original.split_dates = vec![String::from("2024-01-05")];
+    original.prob_threshold = Some(0.6);
+    original.accuracy = 0.6571429;
+    original.confusion = [[3, 1], [2, 4]];
+
+    let text = original.to_json().to_string();
+    let reloaded = AnalysisResult::from_json(&serde_json::from_str(&text).unwrap()).unwrap();
+
+    assert_eq!(reloaded, original);
+}
+
+#[test]
+fn precision_rounds_prices_and_percentages() {
+    let money = MoneyFormat {
+        currency: String::from("$"),
+        thousands: true,
+        precision: Some(0),
+        color: false,
+    };
+    assert_eq!(money.format(1234.56), "$1,235");
+    assert_eq!(money.percent(0.65714), "66");
+
+    let money = MoneyFormat {
+        precision: Some(4),
+        ..money
+    };
+    assert_eq!(money.format(-1234.56789), "-$1,234.5679");
+    assert_eq!(money.percent(0.65714), "65.7140");
+
+    let text = result("precise.csv", 104.123456, Direction::Increase).to_text(&money);
+    assert!(text.contains("$104.1235"));
+    assert!(text.contains("test accuracy of 50.0000%"));
+}
+
+#[test]
+fn default_precision_rounds_accuracies_without_float_noise() {
+    let money = MoneyFormat::default();
+    assert_eq!(money.format(104.123456), "104.12");
+    assert_eq!(money.accuracy(0.6), "60");
+    assert_eq!(money.accuracy(0.6571429), "65.7143");
+    assert_eq!(money.percent(0.125), "12.5");
+
+    let mut noisy = result("noisy.csv", 110.0, Direction::Increase);
+    noisy.accuracy = 0.6;
+    assert!(noisy.to_text(&money).contains("test accuracy of 60%"));
+}
+
+#[test]
+fn color_paints_increases_green_and_decreases_red() {
+    let money = MoneyFormat {
+        color: true,
+        ..MoneyFormat::default()
+    };
+    assert_eq!(money.paint("up", 0.5), "\x1b[32mup\x1b[0m");
+    assert_eq!(money.paint("down", -0.5), "\x1b[31mdown\x1b[0m");
+    assert_eq!(money.paint("flat", 0.0), "flat");
+
+    let text = result("colored.csv", 90.0, Direction::Decrease).to_text(&money);
+    assert!(text.contains("predicts \x1b[31ma decrease\x1b[0m"));
+
+    // the plain output has no escapes and the table stays aligned when colored
+    let plain = format_table(
+        &[result("colored.csv", 90.0, Direction::Decrease)],
+        &MoneyFormat::default(),
+    );
+    assert!(!plain.contains('\x1b'));
+    let colored = format_table(&[result("colored.csv", 90.0, Direction::Decrease)], &money);
+    let stripped = colored.replace("\x1b[31m", "").replace("\x1b[0m", "");
+    assert_eq!(stripped, plain);
+}
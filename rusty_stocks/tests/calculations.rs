@@ -0,0 +1,531 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use rusty_stocks::calculations::{
+    calculate_beta, calculate_drift, calculate_final_prices, calculate_returns,
+    calculate_rolling_beta, check_training_set, detect_splits, discounted_expected_price,
+    engineer_features, expected_out_of_bag, explain_prediction, find_stale_runs,
+    first_passage_days, fraction_above, fraction_below, implied_volatility, lag1_autocorrelation,
+    log_returns, merge_series, recency_copies, run_forest, split_data, tally_votes, train_votes,
+    transform_volumes, weighted_share, ForestOptions, ForestVote, MergeMode, SimulationOptions,
+    SplitCriterion, VolumeTransform,
+};
+use rusty_stocks::stock::{ReturnType, Stock, Tomorrow};
+
+fn make_stocks(count: usize) -> Vec<Stock> {
+    (0..count)
+        .map(|day| {
+            let price = 100.0 + day as f64;
+            Stock::new(
+                format!("2024-01-{:02}", day + 1),
+                price,
+                price + 1.0,
+                price - 1.0,
+                price,
+                price,
+                1000 + day as u64,
+                Tomorrow::Increase,
+            )
+        })
+        .collect()
+}
+
+fn dates(stocks: &[Stock]) -> Vec<String> {
+    stocks.iter().map(|stock| stock.get_date_string()).collect()
+}
+
+#[test]
+fn split_data_is_reproducible_with_seeded_rng() {
+    let stocks = make_stocks(20);
+
+    let (train_a, test_a) = split_data(&stocks, 0.75, &mut StdRng::seed_from_u64(42));
+    let (train_b, test_b) = split_data(&stocks, 0.75, &mut StdRng::seed_from_u64(42));
+
+    assert_eq!(train_a.len(), 15);
+    assert_eq!(test_a.len(), 5);
+    assert_eq!(dates(&train_a), dates(&train_b));
+    assert_eq!(dates(&test_a), dates(&test_b));
+}
+
+#[test]
+fn log_returns_match_known_series() {
+    let mut stocks = make_stocks(3);
+    for i in 1..stocks.len() {
+        let curr_price = stocks[i - 1].get_price();
+        stocks[i].set_return(curr_price, ReturnType::Log);
+    }
+
+    let returns = calculate_returns(&stocks, ReturnType::Log);
+
+    // prices are (high + close) / 2, so 100.5, 101.5 and 102.5
+    assert_eq!(returns.len(), 2);
+    assert!((returns[0] - (101.5f64 / 100.5).ln()).abs() < 1e-12);
+    assert!((returns[1] - (102.5f64 / 101.5).ln()).abs() < 1e-12);
+    assert_eq!(returns[0], stocks[1].get_return());
+    assert_eq!(returns[1], stocks[2].get_return());
+    assert_eq!(log_returns(&stocks), returns);
+
+    // calculate_drift also counts the zero return of the first stock
+    let all: Vec<f64> = vec![0.0, returns[0], returns[1]];
+    let mean: f64 = all.iter().sum::<f64>() / 3.0;
+    let var: f64 = all.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / 3.0;
+    let (drift, variance) = calculate_drift(&stocks, None);
+    assert!((variance - var).abs() < 1e-12);
+    assert!((drift - (mean - 0.5 * var)).abs() < 1e-12);
+}
+
+#[test]
+fn tiny_dataset_keeps_a_test_row() {
+    let mut stocks = make_stocks(3);
+    stocks[0].set_tomorrow(Tomorrow::Decrease);
+
+    let (train, test) = split_data(&stocks, 0.95, &mut StdRng::seed_from_u64(1));
+    assert_eq!((train.len(), test.len()), (2, 1));
+
+    let options = ForestOptions {
+        features: vec![0, 1, 2, 3, 4, 5],
+        invert: true,
+        trees: 10,
+        max_features: None,
+        criterion: SplitCriterion::Gini,
+        train_frac: 0.95,
+        recency_weight: None,
+        max_votes: 10,
+        target_stability: None,
+    };
+    // one training row is too few to fit a forest on, which is an error rather than a meaningless model
+    let error = run_forest(&stocks, &options, 2, &mut StdRng::seed_from_u64(0)).unwrap_err();
+    assert!(error.to_string().contains("at least 2 are needed"));
+}
+
+#[test]
+fn training_set_needs_rows_of_both_labels() {
+    let mut stocks = make_stocks(4);
+
+    assert!(check_training_set(&[]).is_err());
+    assert!(check_training_set(&stocks).is_err());
+
+    stocks[1].set_tomorrow(Tomorrow::Decrease);
+    assert!(check_training_set(&stocks).is_ok());
+}
+
+#[test]
+fn engineer_features_skips_early_rows() {
+    let stocks = make_stocks(25);
+
+    let engineered = engineer_features(&stocks).unwrap();
+
+    assert_eq!(engineered.len(), 6);
+    assert_eq!(engineered[0].get_date_string(), "2024-01-20");
+
+    // rising closes 100..=119: SMA(5) = 117, SMA(20) = 109.5, no losses and 2024-01-20 is a Saturday
+    let features = engineered[0].feature_subset(&[6, 7, 8]);
+    assert!((features[0] - 117.0 / 109.5).abs() < 1e-12);
+    assert_eq!(features[1], 100.0);
+    assert_eq!(features[2], 5.0);
+}
+
+#[test]
+fn detect_splits_flags_a_two_for_one_split() {
+    // the close halves on the third day while the adjusted close keeps rising
+    let rows = [(100.0, 50.0), (102.0, 51.0), (52.0, 52.0), (53.0, 53.0)];
+    let mut stocks: Vec<Stock> = rows
+        .iter()
+        .enumerate()
+        .map(|(day, (close, adj_close))| {
+            Stock::new(
+                format!("2024-01-{:02}", day + 1),
+                *close,
+                *close,
+                *close,
+                *close,
+                *adj_close,
+                1000,
+                Tomorrow::Predict,
+            )
+        })
+        .collect();
+
+    assert_eq!(detect_splits(&stocks, 0.2), vec![2]);
+
+    for stock in stocks.iter_mut() {
+        stock.adjust_prices();
+    }
+    assert_eq!(stocks[0].get_close(), 50.0);
+    assert_eq!(stocks[0].get_price(), 50.0);
+    assert!(detect_splits(&stocks, 0.2).is_empty());
+}
+
+#[test]
+fn simulation_rejects_non_finite_parameters() {
+    let mut stocks = make_stocks(5);
+    // a zero price makes one log return infinite and the estimated drift NaN
+    stocks[2] = Stock::new(
+        String::from("2024-01-03"),
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        1000,
+        Tomorrow::Increase,
+    );
+    for i in 1..stocks.len() {
+        let price_yesterday = stocks[i - 1].get_price();
+        stocks[i].set_return(price_yesterday, ReturnType::Log);
+    }
+
+    let options = SimulationOptions {
+        days: 2,
+        trials: 10,
+        risk_free_rate: None,
+        bootstrap: false,
+        start_price: None,
+    };
+
+    assert!(calculate_final_prices(&stocks, &options, 10, &mut StdRng::seed_from_u64(0)).is_err());
+}
+
+#[test]
+fn lag1_autocorrelation_of_alternating_series() {
+    let alternating: Vec<f64> = (0..100)
+        .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+        .collect();
+    let trending: Vec<f64> = (0..100).map(|i| i as f64).collect();
+
+    assert!((lag1_autocorrelation(&alternating) + 0.99).abs() < 1e-12);
+    assert!(lag1_autocorrelation(&trending) > 0.9);
+}
+
+#[test]
+fn beta_of_a_doubled_benchmark_is_two() {
+    let stock = |date: String, price: f64| {
+        Stock::new(
+            date,
+            price,
+            price,
+            price,
+            price,
+            price,
+            1000,
+            Tomorrow::Predict,
+        )
+    };
+    let benchmark_prices = [100.0, 101.0, 99.5, 102.0, 103.0, 101.5];
+
+    // the stock has twice the log return of the benchmark on every day and misses the third day
+    let benchmark: Vec<Stock> = benchmark_prices
+        .iter()
+        .enumerate()
+        .map(|(day, price)| stock(format!("2024-01-{:02}", day + 1), *price))
+        .collect();
+    let stocks: Vec<Stock> = benchmark_prices
+        .iter()
+        .enumerate()
+        .filter(|(day, _)| *day != 2)
+        .map(|(day, price)| stock(format!("2024-01-{:02}", day + 1), price * price))
+        .collect();
+
+    let (beta, alpha) = calculate_beta(&stocks, &benchmark, ReturnType::Log).unwrap();
+    assert!((beta - 2.0).abs() < 1e-9);
+    assert!(alpha.abs() < 1e-9);
+
+    assert!(calculate_beta(&stocks[..2], &benchmark, ReturnType::Log).is_err());
+
+    // four returns on shared dates give three windows of two, each dated by its last day
+    let rolling = calculate_rolling_beta(&stocks, &benchmark, 2, ReturnType::Log).unwrap();
+    let dates: Vec<&str> = rolling.iter().map(|(date, _)| date.as_str()).collect();
+    assert_eq!(dates, ["2024-01-04", "2024-01-05", "2024-01-06"]);
+    assert!(rolling.iter().all(|(_, beta)| (beta - 2.0).abs() < 1e-9));
+    assert!(calculate_rolling_beta(&stocks, &benchmark, 5, ReturnType::Log).is_err());
+}
+
+#[test]
+fn stale_runs_are_found_up_to_the_last_row() {
+    let mut stocks = make_stocks(12);
+    // rows 2 to 4 and 8 to 11 repeat the row before them
+    for idx in [3, 4, 9, 10, 11] {
+        stocks[idx] = Stock::new(
+            format!("2024-01-{:02}", idx + 1),
+            stocks[idx - 1].get_array()[0],
+            stocks[idx - 1].get_array()[1],
+            stocks[idx - 1].get_array()[2],
+            stocks[idx - 1].get_close(),
+            stocks[idx - 1].get_adj_close(),
+            1000,
+            Tomorrow::Predict,
+        );
+    }
+
+    assert_eq!(find_stale_runs(&stocks, 3), vec![(2, 4), (8, 11)]);
+    assert_eq!(find_stale_runs(&stocks, 4), vec![(8, 11)]);
+}
+
+#[test]
+fn first_passage_finds_the_first_day_at_the_target() {
+    // three paths from 100: one rises, one falls, one stays flat
+    let price_paths = vec![
+        vec![100.0, 100.0, 100.0],
+        vec![105.0, 95.0, 100.0],
+        vec![111.0, 89.0, 100.0],
+        vec![108.0, 92.0, 100.0],
+    ];
+
+    assert_eq!(
+        first_passage_days(&price_paths, 110.0),
+        vec![Some(2), None, None]
+    );
+    assert_eq!(
+        first_passage_days(&price_paths, 90.0),
+        vec![None, Some(2), None]
+    );
+    assert_eq!(
+        first_passage_days(&price_paths, 100.0),
+        vec![Some(0), Some(0), Some(0)]
+    );
+}
+
+#[test]
+fn fractions_above_and_below_exclude_the_threshold() {
+    let prices = [90.0, 100.0, 110.0, 120.0];
+
+    assert_eq!(fraction_above(&prices, 100.0), 0.5);
+    assert_eq!(fraction_below(&prices, 100.0), 0.25);
+    assert_eq!(fraction_above(&prices, 150.0), 0.0);
+    assert_eq!(fraction_below(&prices, 150.0), 1.0);
+}
+
+#[test]
+fn implied_volatility_annualizes_the_log_returns() {
+    // two paths whose log return to day 1 is +-0.01 and to day 4 is +-0.02
+    let e = std::f64::consts::E;
+    let mut price_paths = vec![vec![100.0, 100.0]; 5];
+    price_paths[1] = vec![100.0 * e.powf(0.01), 100.0 * e.powf(-0.01)];
+    price_paths[4] = vec![100.0 * e.powf(0.02), 100.0 * e.powf(-0.02)];
+
+    assert!((implied_volatility(&price_paths, 1, 252.0) - 0.01 * 252f64.sqrt()).abs() < 1e-12);
+    assert!((implied_volatility(&price_paths, 4, 252.0) - 0.01 * 252f64.sqrt()).abs() < 1e-12);
+    assert_eq!(implied_volatility(&price_paths, 2, 252.0), 0.0);
+}
+
+#[test]
+fn discounted_expected_price_averages_discounted_means() {
+    let price_paths = vec![vec![100.0, 100.0], vec![100.0, 120.0], vec![90.0, 130.0]];
+
+    assert_eq!(discounted_expected_price(&price_paths, &[1, 2], 0.0), 110.0);
+
+    let discounted = discounted_expected_price(&price_paths, &[1, 2], 0.1);
+    let expected = ((-0.1f64).exp() * 110.0 + (-0.2f64).exp() * 110.0) / 2.0;
+    assert!((discounted - expected).abs() < 1e-12);
+}
+
+#[test]
+fn explanation_credits_the_feature_the_labels_follow() {
+    // the label only depends on the open, the volume never changes
+    let stocks: Vec<Stock> = (0..100)
+        .map(|day| {
+            let open = day as f64;
+            let tomorrow = if day >= 50 {
+                Tomorrow::Increase
+            } else {
+                Tomorrow::Decrease
+            };
+            Stock::new(
+                String::from("2024-01-01"),
+                open,
+                200.0,
+                0.0,
+                100.0,
+                100.0,
+                1000,
+                tomorrow,
+            )
+        })
+        .collect();
+    let options = ForestOptions {
+        features: vec![0, 5],
+        invert: false,
+        trees: 20,
+        max_features: None,
+        criterion: SplitCriterion::Gini,
+        train_frac: 0.8,
+        recency_weight: None,
+        max_votes: 10,
+        target_stability: None,
+    };
+
+    let forests = train_votes(&stocks, &options, 99, &mut StdRng::seed_from_u64(0)).unwrap();
+    let contributions = explain_prediction(&forests, &stocks, &options.features, 99);
+
+    assert_eq!(contributions[0].0, 0);
+    assert!(contributions[0].1 > 0.5);
+    assert_eq!(contributions[1], (5, 0.0));
+}
+
+#[test]
+fn weighted_vote_weights_forests_by_accuracy() {
+    let mut stocks = make_stocks(40);
+    for stock in stocks.iter_mut().step_by(2) {
+        stock.set_tomorrow(Tomorrow::Decrease);
+    }
+    let options = ForestOptions {
+        features: vec![0, 1, 2, 3, 4, 5],
+        invert: true,
+        trees: 5,
+        max_features: None,
+        criterion: SplitCriterion::Gini,
+        train_frac: 0.7,
+        recency_weight: None,
+        max_votes: 10,
+        target_stability: None,
+    };
+
+    let forests = train_votes(&stocks, &options, 39, &mut StdRng::seed_from_u64(0)).unwrap();
+    let vote = tally_votes(&forests, &stocks[39], &options.features);
+
+    let (mut increase, mut total) = (0.0, 0.0);
+    for forest in &forests {
+        let result = forest.predict(&stocks[39], &options.features);
+        total += result.accuracy as f64;
+        if result.prediction == Tomorrow::Increase.get_label() {
+            increase += result.accuracy as f64;
+        }
+    }
+    assert!((vote.weighted_increase - increase / total).abs() < 1e-12);
+}
+
+#[test]
+fn weighted_share_counts_forests_alike_without_accuracy() {
+    assert_eq!(weighted_share(0.25, 0.5, 1, 2), 0.5);
+    assert_eq!(weighted_share(0.0, 0.0, 3, 4), 0.75);
+    assert_eq!(weighted_share(0.0, 0.0, 0, 4), 0.0);
+}
+
+#[test]
+fn volume_rank_is_taken_against_the_training_window() {
+    let mut stocks = make_stocks(10);
+
+    transform_volumes(&mut stocks, VolumeTransform::Rank, 4);
+
+    let ranks: Vec<f64> = stocks.iter().map(|stock| stock.get_array()[5]).collect();
+    assert_eq!(&ranks[..4], &[0.0, 0.25, 0.5, 0.75]);
+    // later rows are above every training volume, they do not change the window
+    assert!(ranks[4..].iter().all(|rank| *rank == 1.0));
+
+    transform_volumes(&mut stocks, VolumeTransform::Log, 4);
+    assert!((stocks[0].get_array()[5] - 1001.0_f64.ln()).abs() < 1e-12);
+
+    transform_volumes(&mut stocks, VolumeTransform::None, 4);
+    assert_eq!(stocks[9].get_array()[5], 1009.0);
+}
+
+#[test]
+fn recency_copies_decay_with_age() {
+    assert_eq!(recency_copies(0, 0.1), 5);
+    assert_eq!(recency_copies(7, 0.1), 2);
+    // old rows are never dropped
+    assert_eq!(recency_copies(1000, 0.1), 1);
+}
+
+#[test]
+fn out_of_bag_rows_match_the_bootstrap_limit() {
+    // (1 - 1/n)^n tends to 1/e
+    let out_of_bag = expected_out_of_bag(&[1; 1000]);
+    assert!((out_of_bag / 1000.0 - (-1.0_f64).exp()).abs() < 1e-3);
+
+    // a row with most of the copies is almost never left out
+    let weighted = expected_out_of_bag(&[8, 1, 1]);
+    assert!((weighted - (0.2_f64.powi(10) + 2.0 * 0.9_f64.powi(10))).abs() < 1e-12);
+}
+
+#[test]
+fn forest_vote_stops_once_the_accuracy_settles() {
+    let mut stocks = make_stocks(40);
+    for stock in stocks.iter_mut().step_by(2) {
+        stock.set_tomorrow(Tomorrow::Decrease);
+    }
+    let options = ForestOptions {
+        features: vec![0, 1, 2, 3, 4, 5],
+        invert: true,
+        trees: 5,
+        max_features: None,
+        criterion: SplitCriterion::Gini,
+        train_frac: 0.7,
+        recency_weight: None,
+        max_votes: 4,
+        target_stability: None,
+    };
+    assert_eq!(
+        train_votes(&stocks, &options, 39, &mut StdRng::seed_from_u64(0))
+            .unwrap()
+            .len(),
+        4
+    );
+
+    // every accuracy is within 1 of the last, so the second forest already settles it
+    let settled = ForestOptions {
+        target_stability: Some(1.5),
+        ..options
+    };
+    assert_eq!(
+        train_votes(&stocks, &settled, 39, &mut StdRng::seed_from_u64(0))
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn agreement_is_the_share_of_the_majority() {
+    let vote = ForestVote {
+        num_inc: 3,
+        num_dec: 7,
+        weighted_increase: 0.3,
+        probability: 0.3,
+        accuracy: 0.6,
+        raw_accuracy: 0.6,
+        confusion: [[0; 2]; 2],
+    };
+    assert_eq!(vote.agreement(), 0.7);
+
+    let split = ForestVote {
+        num_inc: 2,
+        num_dec: 2,
+        ..vote
+    };
+    assert_eq!(split.agreement(), 0.5);
+}
+
+#[test]
+fn chain_merge_joins_files_without_a_jump() {
+    // the second file repeats the dates of the first at twice the price and continues them
+    let first = make_stocks(10);
+    let mut second = make_stocks(20);
+    for stock in second.iter_mut() {
+        stock.scale(2.0);
+    }
+    let series = vec![
+        (String::from("first.csv"), first.clone()),
+        (String::from("second.csv"), second.clone()),
+    ];
+
+    let (concat, ratios) = merge_series(&series, MergeMode::Concat, ReturnType::Log).unwrap();
+    assert_eq!(ratios, vec![1.0]);
+    assert_eq!(concat.len(), 20);
+    assert_eq!(concat[10].get_price(), second[10].get_price());
+
+    let (chained, ratios) = merge_series(&series, MergeMode::Chain, ReturnType::Log).unwrap();
+    assert_eq!(ratios, vec![0.5]);
+    assert_eq!(dates(&chained), dates(&second));
+    assert_eq!(chained[9].get_price(), first[9].get_price());
+    assert!((chained[10].get_price() - 110.5).abs() < 1e-9);
+    assert_eq!(chained[10].get_volume(), 1010);
+    assert!(chained[19].get_label() == Tomorrow::Predict.get_label());
+
+    // a file ending before the series so far adds nothing
+    let stale = vec![
+        (String::from("second.csv"), second),
+        (String::from("first.csv"), first),
+    ];
+    assert!(merge_series(&stale, MergeMode::Chain, ReturnType::Log).is_err());
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use chrono::NaiveDate;
+
+use rusty_stocks::calendar::TradingCalendar;
+
+fn date(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 7, day).unwrap()
+}
+
+#[test]
+fn weekends_are_skipped() {
+    let calendar = TradingCalendar::default();
+
+    // Friday the 5th to Monday the 8th is one trading day
+    assert_eq!(calendar.trading_days_between(date(5), date(8)), 1);
+    assert_eq!(calendar.add_trading_days(date(5), 1), date(8));
+    assert_eq!(calendar.trading_days_between(date(1), date(31)), 22);
+    assert_eq!(calendar.trading_days_between(date(8), date(5)), 0);
+}
+
+#[test]
+fn holidays_are_skipped_like_weekends() {
+    let path = common::write_temp_file(
+        "holidays.txt",
+        "# US market holidays\n2024-07-04\n\n2024-12-25\n",
+    );
+    let calendar = TradingCalendar::load(path.to_str().unwrap()).unwrap();
+
+    assert!(!calendar.is_trading_day(date(4)));
+    assert_eq!(calendar.add_trading_days(date(3), 1), date(5));
+    assert_eq!(calendar.trading_days_between(date(1), date(31)), 21);
+
+    for days in 0..30 {
+        let reached = calendar.add_trading_days(date(1), days);
+        assert_eq!(calendar.trading_days_between(date(1), reached), days);
+    }
+}
+
+#[test]
+fn a_bad_holiday_names_its_line() {
+    let path = common::write_temp_file("bad_holidays.txt", "2024-07-04\nJuly 5th\n");
+
+    let err = TradingCalendar::load(path.to_str().unwrap()).unwrap_err();
+
+    assert!(err.to_string().contains("line 2: invalid date July 5th"));
+}
@@ -0,0 +1,644 @@
+mod common;
+
+use std::process::Command;
+
+#[test]
+fn decimal_comma_and_scientific_notation_parse() {
+    let path = common::write_temp_file(
+        "decimal_comma.csv",
+        "Date;Open;High;Low;Close;Adj Close;Volume\n\
+         2024-01-02;1.000,50;1.010,25;990,75;1.005,00;1.005,00;1.234.567\n\
+         2024-01-03;1,005E+03;1,02E+03;1,0E+03;1,015E+03;1,015E+03;2.345.678\n\
+         2024-01-04;1.015,00;1.030,00;1.010,00;1.025,00;1.025,00;3.456.789\n",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--params-only", "--decimal-comma", "--delimiter", ";"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let params: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(params["variance"].as_f64().unwrap() > 0.0);
+    assert_eq!(params["schema_version"], rusty_stocks::JSON_SCHEMA_VERSION);
+}
+
+#[test]
+fn decimal_comma_requires_another_delimiter() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--decimal-comma", "tests/inputs/Amazon.csv"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--decimal-comma"));
+}
+
+#[test]
+fn money_format_groups_thousands() {
+    let money = rusty_stocks::analysis::MoneyFormat {
+        currency: String::from("$"),
+        thousands: true,
+        precision: None,
+        color: false,
+    };
+
+    assert_eq!(money.format(1234567.891), "$1,234,567.89");
+    assert_eq!(money.format(999.999), "$1,000.00");
+    assert_eq!(money.format(-42.5), "-$42.50");
+}
+
+#[test]
+fn failed_file_does_not_stop_the_batch() {
+    let broken = common::write_temp_file(
+        "broken.csv",
+        "Date,Open,High,Low,Close,Adj Close,Volume\n2024-01-02,1,2,oops,1,1,100\n",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--params-only")
+        .arg(&broken)
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .arg("tests/inputs/missing.csv")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 1);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 of 3 files analyzed, 2 failed"));
+}
+
+#[test]
+fn failed_plot_exits_with_an_error() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["plot", "tests/inputs/missing.csv"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error: "));
+}
+
+#[test]
+fn glob_adds_matching_files() {
+    let first = common::synthetic_csv("glob_first.csv", 0.0, 0.01, 30, 1);
+    common::synthetic_csv("glob_second.csv", 0.0, 0.01, 30, 2);
+    let pattern = first.with_file_name("glob_*.csv");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--params-only")
+        .arg("--glob")
+        .arg(&pattern)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 2);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("matched 2 files"));
+}
+
+#[test]
+fn config_file_settings_are_overridden_by_flags() {
+    let settings = common::write_temp_file("settings.toml", "period = \"1wk\"\nstrict = true\n");
+    let period = |extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .arg("--config")
+            .arg(&settings)
+            .args(extra)
+            .args(["--params-only", "tests/inputs/Apple Stock Historical.csv"])
+            .output()
+            .unwrap();
+        let params: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        params["period"].as_str().unwrap().to_string()
+    };
+
+    assert_eq!(period(&[]), "1wk");
+    assert_eq!(period(&["--period", "1mo"]), "1mo");
+
+    let unknown = common::write_temp_file("unknown.toml", "perod = \"1wk\"\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--config")
+        .arg(&unknown)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("perod"));
+}
+
+#[test]
+fn returns_out_writes_the_log_returns() {
+    let path = std::env::temp_dir().join("rusty_stocks_returns_out.csv");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--returns-out")
+        .arg(&path)
+        .args(["--params-only", "tests/inputs/Apple Stock Historical.csv"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("date,log_return"));
+    // the header of the input and its first row have no return
+    assert_eq!(lines.count(), 61);
+}
+
+#[test]
+fn dump_table_writes_the_training_rows() {
+    let path = std::env::temp_dir().join("rusty_stocks_dump_table.csv");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--dump-table")
+        .arg(&path)
+        .args(["--features", "close,volume"])
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("date,close,volume,label"));
+    // the last row is predicted and has no label to train on
+    let rows: Vec<&str> = lines.collect();
+    assert_eq!(rows.len(), 61);
+    assert!(rows
+        .iter()
+        .all(|row| row.ends_with(",0") || row.ends_with(",1")));
+}
+
+#[test]
+fn since_and_until_filter_the_rows() {
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args(extra)
+            .args(["--params-only", "--returns-out"])
+            .arg(std::env::temp_dir().join("rusty_stocks_since_until.csv"))
+            .arg("tests/inputs/Apple Stock Historical.csv")
+            .output()
+            .unwrap()
+    };
+
+    assert!(run(&["--since", "2024-02-01", "--until", "2024-02-29"])
+        .status
+        .success());
+    let returns =
+        std::fs::read_to_string(std::env::temp_dir().join("rusty_stocks_since_until.csv")).unwrap();
+    let dates: Vec<&str> = returns.lines().skip(1).map(|line| &line[0..10]).collect();
+    assert!(dates.iter().all(|date| date.starts_with("2024-02")));
+
+    let output = run(&["--since", "2030-01-01"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("leave 0 rows"));
+}
+
+#[test]
+fn metrics_report_the_sample_the_drift_is_estimated_from() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--params-only", "--metrics", "--since", "2024-02-01"])
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let params: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(params["rows_loaded"], 62);
+    let used = params["rows_used"].as_u64().unwrap();
+    assert!(used < 62);
+    assert_eq!(params["drift_returns"], used);
+    assert_eq!(params["effective_sample_size"], used);
+}
+
+#[test]
+fn ndjson_stats_prints_a_line_per_forest() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--format", "ndjson-stats", "--forest-max-votes", "4"])
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let records: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 4);
+    for (iteration, record) in records.iter().enumerate() {
+        assert_eq!(record["iteration"], iteration);
+        assert!(["increase", "decrease"].contains(&record["direction"].as_str().unwrap()));
+        assert!(record["accuracy"].as_f64().unwrap() >= 0.0);
+        assert!(record["inverted"].is_boolean());
+    }
+}
+
+#[test]
+fn group_by_direction_rejects_streaming_formats() {
+    for format in ["ndjson-stats", "json"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args(["--group-by-direction", "--format", format])
+            .arg("tests/inputs/Apple Stock Historical.csv")
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("--group-by-direction can only be used with --format text or table"));
+    }
+}
+
+#[test]
+fn provenance_only_hashes_the_prediction_settings() {
+    let report = std::env::temp_dir().join("rusty_stocks_provenance_report.md");
+    let provenance = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args(["--params-only", "--seed", "1"])
+            .args(args)
+            .arg("tests/inputs/Apple Stock Historical.csv")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let params: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        params["provenance"].as_str().unwrap().to_string()
+    };
+
+    let plain = provenance(&[]);
+    assert_eq!(
+        plain,
+        provenance(&["--threads", "2", "--report", report.to_str().unwrap()])
+    );
+    assert_ne!(plain, provenance(&["--trials", "500"]));
+}
+
+#[test]
+fn help_examples_parse_as_command_lines() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--help-examples")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let examples: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("cargo run -- "))
+        .collect();
+    assert!(examples.len() >= 5);
+
+    // --list-features exits before any file is read, so only the flags of every example are checked.
+    // --from-repro reads its manifest before the flags are parsed and is left out
+    for example in examples {
+        let parsed = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args(
+                example
+                    .split(' ')
+                    .filter(|arg| *arg != "--from-repro" && *arg != "run.json"),
+            )
+            .arg("--list-features")
+            .output()
+            .unwrap();
+        assert!(parsed.status.success(), "{}", example);
+    }
+}
+
+#[test]
+fn print_config_shows_the_resolved_settings() {
+    let config = std::env::temp_dir().join("rusty_stocks_print_config.toml");
+    std::fs::write(&config, "train_frac = 0.5\nstrict = true\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--config")
+        .arg(&config)
+        .args(["--train-frac", "0.25", "--print-config", "--list-features"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // the flag overrides the config file, which still sets strict
+    let printed = String::from_utf8_lossy(&output.stderr);
+    assert!(printed.contains("train_frac: 0.25"));
+    assert!(printed.contains("strict: true"));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("train_frac"));
+}
+
+#[test]
+fn meta_forests_report_their_agreement() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--meta-forests", "3"])
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("of 3 independent forests agree"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--meta-forests", "3", "--forest-max-votes", "5"])
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn days_sets_the_forecast_horizon() {
+    let run = |days: &str| {
+        Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args(["--days", days, "--prob-above", "100"])
+            .arg("tests/inputs/Apple Stock Historical.csv")
+            .output()
+            .unwrap()
+    };
+
+    let output = run("5");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("predict a price of"));
+    assert!(stdout.contains("after 5 days!"));
+    assert!(stdout.contains("The simulated price after 5 days is above"));
+
+    assert!(run("1").status.success());
+
+    let output = run("0");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--days must be at least 1"));
+}
+
+#[test]
+fn trials_sets_the_number_of_random_walks() {
+    let run = |trials: &str| {
+        Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args(["--trials", trials, "--verbose"])
+            .arg("tests/inputs/Apple Stock Historical.csv")
+            .output()
+            .unwrap()
+    };
+
+    let output = run("200");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("with 200 trials"));
+
+    let output = run("0");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--trials must be at least 1"));
+}
+
+#[test]
+fn seed_makes_runs_reproducible() {
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args(["--seed", "42", "--trials", "1000"])
+            .arg("tests/inputs/Apple Stock Historical.csv")
+            .output()
+            .unwrap()
+    };
+
+    let first = run();
+    assert!(first.status.success());
+    assert_eq!(first.stdout, run().stdout);
+}
+
+#[test]
+fn repro_manifest_records_the_drawn_seed() {
+    let manifest = std::env::temp_dir().join("rusty_stocks_seed_manifest.json");
+
+    let first = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--trials", "1000", "--repro-out"])
+        .arg(&manifest)
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .output()
+        .unwrap();
+    assert!(first.status.success());
+
+    let recorded: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&manifest).unwrap()).unwrap();
+    assert!(recorded["arguments"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|argument| argument.as_str().unwrap().starts_with("--seed=")));
+
+    let replayed = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--from-repro")
+        .arg(&manifest)
+        .output()
+        .unwrap();
+    assert!(replayed.status.success());
+    assert_eq!(first.stdout, replayed.stdout);
+}
+
+#[test]
+fn json_format_prints_one_object_per_file() {
+    let synthetic = common::synthetic_csv("synthetic_json.csv", 0.0005, 0.02, 200, 13);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--format", "json", "--trials", "1000"])
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .arg(&synthetic)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let objects: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(objects.len(), 2);
+    assert_eq!(objects[1]["file"], synthetic.to_str().unwrap());
+    for object in &objects {
+        assert_eq!(object["schema_version"], rusty_stocks::JSON_SCHEMA_VERSION);
+        assert!(object["predicted_price"].as_f64().unwrap() > 0.0);
+        assert!(["increase", "decrease"].contains(&object["direction"].as_str().unwrap()));
+        assert!((0.0..=1.0).contains(&object["accuracy"].as_f64().unwrap()));
+    }
+}
+
+#[test]
+fn json_format_prints_nothing_but_json() {
+    let synthetic = common::synthetic_csv("synthetic_json_only.csv", 0.0005, 0.02, 80, 17);
+    let extras: [&[&str]; 8] = [
+        &["--engineered-features"],
+        &["--recency-weight", "1"],
+        &["--volume-buckets", "4"],
+        &["--volume-transform", "rank"],
+        &["--horizon-sweep", "5,10"],
+        &["--horizon-sweep", "5,10", "--vol-term-structure"],
+        &["--horizon-sweep", "5,10", "--discount-rate", "0.05"],
+        &["--provenance"],
+    ];
+
+    for extra in extras {
+        let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args(["--format", "json", "--trials", "200"])
+            .args(extra)
+            .arg(&synthetic)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", extra);
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(stdout.lines().count(), 1, "{:?}", extra);
+        let object: serde_json::Value = serde_json::from_str(stdout.trim())
+            .unwrap_or_else(|e| panic!("{:?} printed {}: {}", extra, stdout, e));
+        assert!(object["predicted_price"].is_number());
+        assert_eq!(object["provenance"].as_str().unwrap().len(), 64);
+    }
+}
+
+#[test]
+fn repro_manifest_replays_the_arguments() {
+    let manifest = std::env::temp_dir().join("rusty_stocks_manifest.json");
+    let replayed = std::env::temp_dir().join("rusty_stocks_replayed.json");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--period", "1wk", "--params-only", "--repro-out"])
+        .arg(&manifest)
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let recorded: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&manifest).unwrap()).unwrap();
+    assert_eq!(recorded["files"][0]["sha256"].as_str().unwrap().len(), 64);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--from-repro")
+        .arg(&manifest)
+        .arg("--repro-out")
+        .arg(&replayed)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let params: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(params["period"], "1wk");
+    let again: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&replayed).unwrap()).unwrap();
+    assert_eq!(again["arguments"], recorded["arguments"]);
+}
+
+#[test]
+fn header_without_rows_is_reported() {
+    let blank = common::write_temp_file(
+        "header_only.csv",
+        "Date,Open,High,Low,Close,Adj Close,Volume\n\n\n\n",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg(&blank)
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!(
+        "{}: no data rows found after header",
+        blank.display()
+    )));
+    assert!(!stderr.contains("panicked"));
+    assert!(stderr.contains("1 of 2 files analyzed, 1 failed"));
+}
+
+#[test]
+fn too_few_rows_are_reported() {
+    let short = common::write_temp_file(
+        "one_row.csv",
+        "Date,Open,High,Low,Close,Adj Close,Volume\n\
+         2024-01-02,10,12,9,11,11,1000\n",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg(&short)
+        .arg("tests/inputs/Apple Stock Historical.csv")
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!(
+        "{}: has 1 rows, too few to predict (need at least 3)",
+        short.display()
+    )));
+    assert!(stderr.contains("1 of 2 files analyzed, 1 failed"));
+}
+
+#[test]
+fn list_features_marks_the_selected_ones() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--list-features", "--features", "open,close"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let active: Vec<&str> = stdout
+        .lines()
+        .skip(1)
+        .filter(|line| line.split_whitespace().nth(2) == Some("yes"))
+        .map(|line| line.split_whitespace().nth(1).unwrap())
+        .collect();
+    assert_eq!(active, ["open", "close"]);
+    assert_eq!(stdout.lines().count(), 10);
+}
+
+#[test]
+fn dup_policy_reconciles_rows_of_one_date() {
+    let duplicated = common::write_temp_file(
+        "duplicated.csv",
+        "Date,Open,High,Low,Close,Adj Close,Volume\n\
+         2024-01-02,100,100,100,100,100,10\n\
+         2024-01-03,100,100,100,100,100,10\n\
+         2024-01-03,110,110,110,110,110,10\n\
+         2024-01-04,110,110,110,110,110,10\n\
+         2024-01-05,121,121,121,121,121,10\n",
+    );
+    let second_return = |policy: &str| {
+        let path = std::env::temp_dir().join(format!("rusty_stocks_dup_{}.csv", policy));
+        let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args(["--params-only", "--dup-policy", policy, "--returns-out"])
+            .arg(&path)
+            .arg(&duplicated)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let row = csv.lines().nth(1).unwrap().to_string();
+        assert_eq!(csv.lines().count(), 4);
+        row.split(',').nth(1).unwrap().parse::<f64>().unwrap()
+    };
+
+    assert_eq!(second_return("first"), 0.0);
+    assert!((second_return("last") - 1.1_f64.ln()).abs() < 1e-12);
+    assert!((second_return("average") - 1.05_f64.ln()).abs() < 1e-12);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args(["--params-only", "--strict"])
+        .arg(&duplicated)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("2 rows share the date 2024-01-03"));
+}
+
+#[test]
+fn assert_accuracy_fails_below_the_threshold() {
+    let run = |pct: &str| {
+        Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+            .args([
+                "--assert-accuracy",
+                pct,
+                "tests/inputs/Apple Stock Historical.csv",
+            ])
+            .output()
+            .unwrap()
+    };
+
+    assert!(run("0").status.success());
+
+    let output = run("100");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is below --assert-accuracy 100%"));
+}
@@ -0,0 +1,115 @@
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use rand::distributions::Distribution;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use statrs::distribution::Normal;
+
+use rusty_stocks::stock::{ReturnType, Stock, Tomorrow};
+
+/*
+    Generates synthetic OHLCV stocks following geometric Brownian motion. Every day has open equal to
+    close and high/low one percent around it, so the price used by the crate moves exactly with the
+    simulated log returns
+
+    @param (drift: f64) mean of the daily log returns
+    @param (vol: f64) standard deviation of the daily log returns
+    @param (length: usize) number of days to generate
+    @param (seed: u64) seed of the random number generator
+
+    @return (Vec<Stock>) generated stocks with labels and returns set the same way run sets them
+*/
+pub fn synthetic_stocks(drift: f64, vol: f64, length: usize, seed: u64) -> Vec<Stock> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let normal = Normal::new(drift, vol).unwrap();
+
+    let mut close: f64 = 100.0;
+    let mut stocks: Vec<Stock> = Vec::new();
+
+    for day in 0..length {
+        if day > 0 {
+            close *= normal.sample(&mut rng).exp();
+        }
+
+        let date =
+            chrono::NaiveDate::from_ymd_opt(2000, 1, 1).unwrap() + chrono::Days::new(day as u64);
+        stocks.push(Stock::new(
+            date.format("%Y-%m-%d").to_string(),
+            close,
+            close * 1.01,
+            close * 0.99,
+            close,
+            close,
+            1_000_000 + day as u64,
+            Tomorrow::Predict,
+        ));
+    }
+
+    for i in 0..(length - 1) {
+        let tomorrow = if stocks[i].get_price() <= stocks[i + 1].get_price() {
+            Tomorrow::Increase
+        } else {
+            Tomorrow::Decrease
+        };
+        stocks[i].set_tomorrow(tomorrow);
+
+        let curr_price = stocks[i].get_price();
+        stocks[i + 1].set_return(curr_price, ReturnType::Log);
+    }
+
+    stocks
+}
+
+/*
+    Writes synthetic GBM data to a CSV file in the Yahoo Finance layout inside the temp directory
+
+    @param (name: &str) file name, unique per test
+    @param (drift: f64) mean of the daily log returns
+    @param (vol: f64) standard deviation of the daily log returns
+    @param (length: usize) number of days to generate
+    @param (seed: u64) seed of the random number generator
+
+    @return (PathBuf) path of the written CSV file
+*/
+pub fn synthetic_csv(name: &str, drift: f64, vol: f64, length: usize, seed: u64) -> PathBuf {
+    let stocks = synthetic_stocks(drift, vol, length, seed);
+
+    let mut csv = String::from("Date,Open,High,Low,Close,Adj Close,Volume\n");
+    for stock in stocks {
+        let [open, high, low, adj_close, close, volume] = stock.get_array();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            stock.get_date_string(),
+            open,
+            high,
+            low,
+            close,
+            adj_close,
+            volume
+        ));
+    }
+
+    write_temp_file(name, &csv)
+}
+
+/*
+    Writes the given contents to a file in the temp directory
+
+    @param (name: &str) file name, unique per test
+    @param (contents: &str) contents of the file
+
+    @return (PathBuf) path of the written file
+*/
+pub fn write_temp_file(name: &str, contents: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rusty_stocks_tests_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+
+    path
+}
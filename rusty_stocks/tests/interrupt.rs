@@ -0,0 +1,33 @@
+mod common;
+
+use std::sync::atomic::Ordering;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use rusty_stocks::calculations::{
+    calculate_final_prices, calculate_price_paths, SimulationOptions,
+};
+
+// INTERRUPTED is global, so this is the only test of its binary
+#[test]
+fn interrupt_stops_the_simulation() {
+    let stocks = common::synthetic_stocks(0.0005, 0.02, 200, 5);
+    let options = SimulationOptions {
+        days: 30,
+        trials: 1000,
+        risk_free_rate: None,
+        bootstrap: false,
+        start_price: None,
+    };
+    let mut rng: StdRng = StdRng::seed_from_u64(1);
+
+    rusty_stocks::INTERRUPTED.store(true, Ordering::Relaxed);
+
+    let err = calculate_price_paths(&stocks, &options, &mut rng).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "interrupted during the Monte Carlo simulation"
+    );
+    assert!(calculate_final_prices(&stocks, &options, 100, &mut rng).is_err());
+}
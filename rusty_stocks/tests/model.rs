@@ -0,0 +1,53 @@
+mod common;
+
+use rusty_stocks::calculations::{
+    tally_votes, train_votes, ForestOptions, SplitCriterion, TrainedForest,
+};
+use rusty_stocks::model::{load_model, save_model, training_fingerprint};
+
+#[test]
+fn saved_model_reloads_with_the_same_votes() {
+    let stocks = common::synthetic_stocks(0.0005, 0.02, 60, 3);
+    let options = ForestOptions {
+        features: vec![0, 1, 2, 3, 4, 5],
+        invert: true,
+        trees: 10,
+        max_features: None,
+        criterion: SplitCriterion::Gini,
+        train_frac: 0.9,
+        recency_weight: None,
+        max_votes: 10,
+        target_stability: None,
+    };
+    let predict_index = stocks.len() - 1;
+
+    let forests: Vec<TrainedForest> =
+        train_votes(&stocks, &options, predict_index, &mut rand::thread_rng()).unwrap();
+    let fingerprint = training_fingerprint(&stocks, &options.features, predict_index);
+
+    let path = common::write_temp_file("model.bin", "");
+    save_model(
+        path.to_str().unwrap(),
+        fingerprint,
+        &options.features,
+        &forests,
+    )
+    .unwrap();
+    let model = load_model(path.to_str().unwrap()).unwrap();
+
+    assert_eq!(model.fingerprint, fingerprint);
+    assert_eq!(model.features, options.features);
+
+    let before = tally_votes(&forests, &stocks[predict_index], &options.features);
+    let after = tally_votes(&model.forests, &stocks[predict_index], &options.features);
+    // the vote of a forest breaks tied trees at random, so only the tree counts are compared
+    assert_eq!(before.probability, after.probability);
+    assert_eq!(before.accuracy, after.accuracy);
+    assert_eq!(before.confusion, after.confusion);
+
+    // the fingerprint only covers the rows the forests were trained on
+    assert_ne!(
+        training_fingerprint(&stocks, &options.features, predict_index - 1),
+        fingerprint
+    );
+}
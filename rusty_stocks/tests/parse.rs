@@ -0,0 +1,172 @@
+use std::io::Cursor;
+
+use rusty_stocks::stock::ReturnType;
+use rusty_stocks::{column_stats, parse_stocks, ParseConfig};
+
+const CSV: &str = "Date,Open,High,Low,Close,Adj Close,Volume\n\
+                   2024-01-02,10,12,9,11,11,1000\n\
+                   \n\
+                   2024-01-03,11,13,10,12,12,2000\n\
+                   2024-01-04,12,12,8,9,9,3000\n";
+
+fn comma_config() -> ParseConfig {
+    ParseConfig {
+        delimiter: ',',
+        decimal_comma: false,
+        strict: false,
+        return_type: ReturnType::Log,
+    }
+}
+
+#[test]
+fn parse_stocks_reads_labels_and_returns() {
+    let stocks = parse_stocks(Cursor::new(CSV), &comma_config()).unwrap();
+
+    assert_eq!(stocks.len(), 3);
+    assert_eq!(stocks[0].get_date_string(), "2024-01-02");
+    assert_eq!(stocks[0].get_array(), [10.0, 12.0, 9.0, 11.0, 11.0, 1000.0]);
+
+    // prices are (high + close) / 2: 11.5, 12.5 and 10.5
+    assert_eq!(stocks[0].get_label(), 1.0);
+    assert_eq!(stocks[1].get_label(), 0.0);
+    assert_eq!(stocks[2].get_label(), -1.0);
+    assert!((stocks[1].get_return() - (12.5f64 / 11.5).ln()).abs() < 1e-12);
+}
+
+#[test]
+fn simple_returns_are_stored_when_chosen() {
+    let simple = ParseConfig {
+        return_type: ReturnType::Simple,
+        ..comma_config()
+    };
+
+    let stocks = parse_stocks(Cursor::new(CSV), &simple).unwrap();
+
+    assert!((stocks[1].get_return() - (12.5 / 11.5 - 1.0)).abs() < 1e-12);
+    assert!((stocks[2].get_return() - (10.5 / 12.5 - 1.0)).abs() < 1e-12);
+}
+
+#[test]
+fn malformed_rows_are_skipped() {
+    let csv = "Date,Open,High,Low,Close,Adj Close,Volume\n\
+               2024-01-02,10,12,9,11,11,1000\n\
+               2024-01-03,11,abc,10,12,12,2000\n\
+               2024-01-04,12,13\n\
+               2024-01-05,12,12,8,9,9,3000\n";
+
+    let stocks = parse_stocks(Cursor::new(csv), &comma_config()).unwrap();
+
+    assert_eq!(stocks.len(), 2);
+    assert_eq!(stocks[1].get_date_string(), "2024-01-05");
+}
+
+#[test]
+fn strict_parsing_names_the_bad_line() {
+    let csv = "Date,Open,High,Low,Close,Adj Close,Volume\n\
+               2024-01-02,10,12,9,11,11,1000\n\
+               2024-01-03,11,abc,10,12,12,2000\n";
+    let strict = ParseConfig {
+        strict: true,
+        ..comma_config()
+    };
+
+    let err = parse_stocks(Cursor::new(csv), &strict).unwrap_err();
+
+    assert!(err.to_string().starts_with("line 3: invalid price"));
+
+    let short = csv.replace("11,abc,10,12,12,2000", "11,13");
+    let err = parse_stocks(Cursor::new(short), &strict).unwrap_err();
+    assert_eq!(err.to_string(), "line 3: 3 fields, expected 7");
+}
+
+#[test]
+fn intraday_datetimes_have_a_date() {
+    let csv = "Datetime,Open,High,Low,Close,Adj Close,Volume\n\
+               2024-01-02 09:30:00,10,12,9,11,11,1000\n\
+               2024-01-02T10:30,11,13,10,12,12,2000\n";
+
+    let stocks = parse_stocks(Cursor::new(csv), &comma_config()).unwrap();
+
+    for stock in stocks {
+        assert_eq!(
+            stock.get_date().unwrap(),
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()
+        );
+    }
+}
+
+#[test]
+fn strict_parsing_names_the_invalid_row() {
+    let csv = "Date,Open,High,Low,Close,Adj Close,Volume\n\
+               2024-01-02,10,12,9,11,11,1000\n\
+               2024-01-03,11,9,10,12,12,2000\n";
+    let strict = ParseConfig {
+        strict: true,
+        ..comma_config()
+    };
+
+    assert!(parse_stocks(Cursor::new(csv), &comma_config()).is_ok());
+
+    let err = parse_stocks(Cursor::new(csv), &strict).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 3: 2024-01-03: the high 9 is below the low 10"
+    );
+}
+
+#[test]
+fn volumes_written_as_floats_are_read() {
+    let csv = "Date,Open,High,Low,Close,Adj Close,Volume\n\
+               2024-01-02,10,12,9,11,11,12345678.0\n\
+               2024-01-03,11,13,10,12,12,1.5e10\n";
+
+    let stocks = parse_stocks(Cursor::new(csv), &comma_config()).unwrap();
+    assert_eq!(stocks[0].get_array()[5], 12345678.0);
+    assert_eq!(stocks[1].get_array()[5], 15000000000.0);
+
+    let negative = csv.replace("12345678.0", "-5");
+    let strict = ParseConfig {
+        strict: true,
+        ..comma_config()
+    };
+    let err = parse_stocks(Cursor::new(negative), &strict).unwrap_err();
+    assert_eq!(err.to_string(), "line 2: invalid volume: -5 is negative");
+}
+
+#[test]
+fn column_stats_count_failures_per_column() {
+    let csv = "Date,Open,High,Low,Close,Adj Close,Volume\n\
+               2024-01-02,10,12,9,11,11,1000\n\
+               2024-01-03,11,13,10,12,12,\"2,000\"\n\
+               01/04/2024,12,12,8,9,9,3000.5\n\
+               2024-01-05,12,12,8,9\n";
+
+    let stats = column_stats(Cursor::new(csv), &comma_config()).unwrap();
+
+    assert_eq!(stats[0].failed, 1);
+    assert_eq!(
+        stats[0].first_failure,
+        Some((4, String::from("01/04/2024")))
+    );
+    assert_eq!((stats[1].parsed, stats[1].failed), (4, 0));
+    assert_eq!(stats[5].first_failure, Some((5, String::from("<missing>"))));
+    // the quoted thousands separator splits the volume into two fields
+    assert_eq!(stats[6].failed, 3);
+    assert_eq!(stats[6].first_failure, Some((3, String::from("\"2"))));
+}
+
+#[test]
+fn mixed_line_endings_and_blank_lines_are_skipped() {
+    // \r\r\n and the final \r leave lines holding only a carriage return
+    let csv = "Date,Open,High,Low,Close,Adj Close,Volume\r\n\
+               2024-01-02,10,12,9,11,11,1000\r\n\
+               \r\r\n\
+               2024-01-03,11,13,10,12,12,2000\n\
+               \r\n   \n\
+               2024-01-04,12,12,8,9,9,3000 \r\n\n\r";
+
+    let stocks = parse_stocks(Cursor::new(csv), &comma_config()).unwrap();
+
+    assert_eq!(stocks.len(), 3);
+    assert_eq!(stocks[2].get_array()[5], 3000.0);
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::process::Command;
+
+use rusty_stocks::analysis::Direction;
+use rusty_stocks::calculations::{
+    ForestOptions, ForestVote, SimulationOptions, SplitCriterion, FOREST_VOTES,
+};
+use rusty_stocks::prediction::{predict, PredictionConfig, PredictionResult};
+use rusty_stocks::stock::ReturnType;
+use rusty_stocks::{parse_stocks, ParseConfig, MIN_ROWS};
+
+fn prediction_config(seed: Option<u64>) -> PredictionConfig {
+    PredictionConfig {
+        simulation: SimulationOptions {
+            days: 10,
+            trials: 1000,
+            risk_free_rate: None,
+            bootstrap: false,
+            start_price: None,
+        },
+        forest: ForestOptions {
+            features: vec![0, 1, 2, 3, 4, 5],
+            invert: true,
+            trees: 10,
+            max_features: None,
+            criterion: SplitCriterion::Gini,
+            train_frac: 0.8,
+            recency_weight: None,
+            max_votes: FOREST_VOTES,
+            target_stability: None,
+        },
+        prob_threshold: None,
+        weighted_vote: false,
+        seed,
+        chunk_size: None,
+        warmup: None,
+        predict_index: None,
+        engineered_features: false,
+        load_model: None,
+    }
+}
+
+#[test]
+fn seeded_predictions_are_reproducible() {
+    let stocks = common::synthetic_stocks(0.0005, 0.02, 200, 9);
+
+    let first: PredictionResult = predict(&stocks, &prediction_config(Some(3))).unwrap();
+    let second: PredictionResult = predict(&stocks, &prediction_config(Some(3))).unwrap();
+
+    assert_eq!(first, second);
+    assert!(first.mean_price > 0.0);
+    assert!((0.0..=1.0).contains(&first.accuracy));
+}
+
+#[test]
+fn predict_needs_three_rows() {
+    let stocks = common::synthetic_stocks(0.0005, 0.02, MIN_ROWS - 1, 9);
+
+    let err = predict(&stocks, &prediction_config(None)).unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "has 2 rows, too few to predict (need at least 3)"
+    );
+}
+
+#[test]
+fn the_cli_prints_the_prediction_of_predict() {
+    let path = common::synthetic_csv("synthetic_predict.csv", 0.0005, 0.02, 60, 21);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .args([
+            "--format", "json", "--seed", "3", "--days", "9", "--trials", "1000",
+        ])
+        .args(["--features", "open,high,low,adj_close,close,volume"])
+        .args(["--train-frac", "0.8", "--forest-max-votes", "10"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let printed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let parse = ParseConfig {
+        delimiter: ',',
+        decimal_comma: false,
+        strict: false,
+        return_type: ReturnType::Log,
+    };
+    let stocks = parse_stocks(BufReader::new(File::open(&path).unwrap()), &parse).unwrap();
+    let mut config = prediction_config(Some(3));
+    config.forest.trees = 100;
+    let prediction = predict(&stocks, &config).unwrap();
+
+    assert_eq!(printed["predicted_price"], prediction.mean_price);
+    assert_eq!(
+        printed["direction"],
+        serde_json::to_value(prediction.direction).unwrap()
+    );
+    assert_eq!(
+        printed["accuracy"].as_f64().unwrap() as f32,
+        prediction.accuracy
+    );
+}
+
+#[test]
+fn prediction_result_applies_the_probability_threshold() {
+    let vote = ForestVote {
+        num_inc: 6,
+        num_dec: 4,
+        weighted_increase: 0.55,
+        probability: 0.55,
+        accuracy: 0.6,
+        raw_accuracy: 0.6,
+        confusion: [[0; 2]; 2],
+    };
+    let prices = [90.0, 110.0];
+
+    let majority = PredictionResult::new(&prices, &vote, None, false);
+    assert_eq!(majority.direction, Direction::Increase);
+    assert_eq!(majority.mean_price, 100.0);
+    assert_eq!(majority.accuracy, 0.6);
+
+    let threshold = PredictionResult::new(&prices, &vote, Some(0.6), false);
+    assert_eq!(threshold.direction, Direction::Uncertain);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use std::process::Command;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use rusty_stocks::calculations::{
+    calculate_drift, calculate_final_prices, RunningStats, SimulationOptions,
+};
+
+const DRIFT: f64 = 0.0005;
+const VOL: f64 = 0.02;
+
+#[test]
+fn calculate_drift_recovers_synthetic_parameters() {
+    let stocks = common::synthetic_stocks(DRIFT, VOL, 5000, 7);
+
+    let (drift, var) = calculate_drift(&stocks, None);
+
+    assert!((var - VOL * VOL).abs() < 5e-5, "variance {}", var);
+    assert!(
+        (drift - (DRIFT - 0.5 * VOL * VOL)).abs() < 1e-3,
+        "drift {}",
+        drift
+    );
+}
+
+#[test]
+fn params_only_recovers_synthetic_parameters_from_csv() {
+    let path = common::synthetic_csv("synthetic_params.csv", DRIFT, VOL, 5000, 11);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_stocks"))
+        .arg("--params-only")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let params: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let var = params["variance"].as_f64().unwrap();
+    let daily_vol = params["daily_vol"].as_f64().unwrap();
+
+    assert!((var - VOL * VOL).abs() < 5e-5, "variance {}", var);
+    assert!((daily_vol - VOL).abs() < 1e-3, "daily vol {}", daily_vol);
+}
+
+#[test]
+fn running_stats_match_calculate_drift() {
+    let stocks = common::synthetic_stocks(DRIFT, VOL, 1000, 11);
+
+    let mut stats = RunningStats::new();
+    for stock in &stocks {
+        stats.push(stock.get_return());
+    }
+
+    let (drift, variance) = calculate_drift(&stocks, None);
+    let (running_drift, running_variance) = stats.drift();
+    assert!((running_drift - drift).abs() < 1e-12);
+    assert!((running_variance - variance).abs() < 1e-12);
+}
+
+#[test]
+fn final_prices_are_reproducible_with_a_seed() {
+    let stocks = common::synthetic_stocks(DRIFT, VOL, 100, 5);
+    let options = SimulationOptions {
+        days: 5,
+        trials: 100,
+        risk_free_rate: None,
+        bootstrap: false,
+        start_price: None,
+    };
+    let simulate = |seed: u64| {
+        calculate_final_prices(&stocks, &options, 30, &mut StdRng::seed_from_u64(seed)).unwrap()
+    };
+
+    assert_eq!(simulate(7), simulate(7));
+    assert_ne!(simulate(7), simulate(8));
+}